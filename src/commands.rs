@@ -1,4 +1,11 @@
-use clap::Parser;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::models::Recurrence;
+use crate::utils::{parse_color, parse_due_date};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -7,51 +14,349 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 #[command(about = "A simple and powerful task manager CLI")]
 #[command(version = VERSION)]
 #[command(arg_required_else_help = true)]
+pub struct Cli {
+    /// use an alternate tasks file instead of the default location
+    #[arg(long = "file", global = true)]
+    pub file: Option<PathBuf>,
+    /// act on this project instead of the current one, without switching
+    #[arg(long = "project", value_name = "PROJECT", global = true)]
+    pub project_context: Option<String>,
+    /// draw plain ASCII markers instead of Unicode symbols (also: TM_ASCII=1)
+    #[arg(long = "ascii", global = true)]
+    pub ascii: bool,
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+#[derive(Subcommand)]
 pub enum Commands {
     /// add a new task or subtask
     #[command(visible_alias = "a")]
     Add {
         /// description of the task
-        text: String,
+        #[arg(required_unless_present_any = ["editor", "stdin"])]
+        text: Option<String>,
         /// nested index path of the parent task (empty for root level)
-        #[arg(required = false)]
+        #[arg(required = false, value_delimiter = '.')]
         path: Vec<usize>,
+        /// add as a subtask of the task with this exact text (case-insensitive)
+        #[arg(short = 'u', long = "under", conflicts_with = "path")]
+        under: Option<String>,
+        /// due date of the task (YYYY-MM-DD)
+        #[arg(long = "due", value_parser = parse_due_date)]
+        due: Option<DateTime<Utc>>,
+        /// priority of the task (1 is highest)
+        #[arg(short = 'P', long = "priority", value_parser = clap::value_parser!(u8).range(1..))]
+        priority: Option<u8>,
+        /// tag to attach to the task (repeatable)
+        #[arg(short = 't', long = "tag")]
+        tags: Vec<String>,
+        /// regenerate the task each time it is completed
+        #[arg(short = 'r', long = "repeat", value_enum)]
+        repeat: Option<Recurrence>,
+        /// write the task text in $EDITOR instead of on the command line
+        #[arg(short = 'e', long = "editor")]
+        editor: bool,
+        /// insert at the start of the list instead of the end
+        #[arg(long = "top", conflicts_with_all = ["after", "before"])]
+        top: bool,
+        /// insert just after the sibling at this index
+        #[arg(long = "after", value_name = "INDEX", conflicts_with = "before")]
+        after: Option<usize>,
+        /// insert just before the sibling at this index
+        #[arg(long = "before", value_name = "INDEX")]
+        before: Option<usize>,
+        /// add each line read from stdin as a root task
+        #[arg(
+            long = "stdin",
+            conflicts_with_all = ["text", "path", "under", "editor", "top", "after", "before"]
+        )]
+        stdin: bool,
     },
     /// list all tasks
     #[command(visible_alias = "l", visible_alias = "ls")]
-    List,
+    List {
+        /// project to show instead of the current one
+        project: Option<String>,
+        /// show every project, one after another
+        #[arg(long = "all", conflicts_with_all = ["project", "json"])]
+        all: bool,
+        /// print the tasks as JSON for scripting
+        #[arg(long = "json")]
+        json: bool,
+        /// print a plain Markdown checklist without indices, colors or banner
+        #[arg(long = "plain", visible_alias = "markdown", conflicts_with = "json")]
+        plain: bool,
+        /// print `<x|o>\t<path>\t<text>` per task for scripts; a stable format
+        #[arg(
+            long = "porcelain",
+            conflicts_with_all = ["all", "json", "plain", "sort", "reverse", "tag", "open", "done", "depth"]
+        )]
+        porcelain: bool,
+        /// order the tasks for display without changing their indices
+        #[arg(long = "sort", value_enum)]
+        sort: Option<SortKey>,
+        /// reverse the display order at each level (newest first unless sorted)
+        #[arg(long = "reverse")]
+        reverse: bool,
+        /// only show tasks carrying this tag (and their ancestors)
+        #[arg(short = 't', long = "tag")]
+        tag: Option<String>,
+        /// only show open tasks (and their ancestors)
+        #[arg(long = "open", conflicts_with = "done")]
+        open: bool,
+        /// only show completed tasks (and their ancestors)
+        #[arg(long = "done")]
+        done: bool,
+        /// hide subtasks nested deeper than this (0 shows only root tasks)
+        #[arg(long = "depth")]
+        depth: Option<usize>,
+        /// don't truncate long lines to the terminal width
+        #[arg(long = "no-wrap")]
+        no_wrap: bool,
+        /// show when each task was created (or completed)
+        #[arg(
+            long = "times",
+            value_enum,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "relative"
+        )]
+        times: Option<TimesFormat>,
+    },
+    /// set or clear the note of a task
+    #[command(visible_alias = "n")]
+    Note {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// note text
+        #[arg(required = true)]
+        text: Option<String>,
+        /// remove the note from the task at this path
+        #[arg(
+            long = "clear",
+            num_args = 1..,
+            value_delimiter = '.',
+            conflicts_with_all = ["text", "path"]
+        )]
+        clear: Option<Vec<usize>>,
+    },
+    /// add tags to a task
+    Tag {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// tag to add (repeatable)
+        #[arg(short = 't', long = "tag", required = true)]
+        tags: Vec<String>,
+    },
+    /// show all details of a task
+    Show {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+    },
+    /// open the first http(s) link in a task's text in the browser
+    Open {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+    },
+    /// search tasks by text (case-insensitive)
+    #[command(visible_alias = "s")]
+    Search {
+        /// text to search for
+        query: String,
+        /// search every project instead of only the current one
+        #[arg(short = 'a', long = "all-projects")]
+        all_projects: bool,
+        /// print only the number of matching tasks
+        #[arg(short = 'c', long = "count", visible_alias = "count-only")]
+        count: bool,
+    },
+    /// change the text of a task, keeping everything else
+    #[command(visible_alias = "rn")]
+    Rename {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// new text of the task
+        text: String,
+    },
+    /// turn every line after the first of a multi-line task into a subtask
+    Split {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+    },
+    /// set the priority of a task (1 is highest)
+    #[command(visible_alias = "pri")]
+    SetPriority {
+        /// the nested index path of the task
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// priority level
+        #[arg(value_parser = clap::value_parser!(u8).range(1..))]
+        level: u8,
+    },
+    /// print the open task to work on next
+    Next {
+        /// pick among open tasks without open subtasks, at any depth
+        #[arg(long = "deep")]
+        deep: bool,
+    },
+    /// show recently completed tasks, newest first
+    #[command(visible_alias = "history")]
+    Log {
+        /// how many tasks to show
+        #[arg(short = 'n', long = "limit", default_value_t = 20)]
+        limit: usize,
+        /// include every project
+        #[arg(short = 'a', long = "all-projects")]
+        all_projects: bool,
+    },
+    /// show tasks due or created today across all projects
+    Today {
+        /// also show open tasks that are past their due date
+        #[arg(long = "overdue")]
+        overdue: bool,
+    },
+    /// list open tasks due today or overdue, e.g. from cron or a login script
+    Remind {
+        /// also show a desktop notification (needs the `notify` feature)
+        #[arg(long = "notify")]
+        notify: bool,
+        /// ring the terminal bell if anything is due
+        #[arg(long = "bell")]
+        bell: bool,
+    },
+    /// show completion statistics
+    #[command(visible_alias = "st")]
+    Stats {
+        /// show a per-project breakdown of every project
+        #[arg(short = 'a', long = "all-projects")]
+        all_projects: bool,
+        /// count tasks completed on or after this date (YYYY-MM-DD)
+        #[arg(long = "since", value_parser = parse_due_date)]
+        since: Option<DateTime<Utc>>,
+        /// output format
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value = "table",
+            conflicts_with = "since"
+        )]
+        format: StatsFormat,
+    },
+    /// print the number of open tasks
+    Count {
+        /// include completed tasks
+        #[arg(short = 'a', long = "all")]
+        all: bool,
+        /// count nested subtasks too
+        #[arg(short = 'r', long = "recursive")]
+        recursive: bool,
+    },
+    /// exit with status 1 if the current project has open tasks, 0 otherwise
+    Pending {
+        /// also print how many tasks are open
+        #[arg(short = 'v', long = "verbose")]
+        verbose: bool,
+    },
     /// mark an item as completed
     #[command(visible_alias = "c")]
     Check {
-        /// the nested index path of the task to complete
+        /// the nested index path of the task to complete, or a range of root
+        /// items such as 2..5 (both ends included)
+        #[arg(required_unless_present_any = ["text", "id"], num_args = 1..)]
+        path: Vec<String>,
+        /// complete the task whose text matches exactly (case-insensitive)
+        #[arg(long = "text", conflicts_with = "path")]
+        text: Option<String>,
+        /// complete the task with this id, wherever it is
+        #[arg(long = "id", conflicts_with_all = ["path", "text"])]
+        id: Option<String>,
+        /// record how the task was resolved, shown by `show` and `log`
+        #[arg(long = "comment")]
+        comment: Option<String>,
+        /// for recurring tasks, replace the task with its next occurrence
+        /// instead of keeping the completed one
+        #[arg(long = "no-history")]
+        no_history: bool,
+    },
+    /// mark every task in the project as completed
+    #[command(visible_alias = "complete-all")]
+    CheckAll {
+        /// skip confirmation prompt
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// mark several root-level items as completed at once
+    #[command(visible_alias = "cm")]
+    CheckMany {
+        /// root-level indices of the tasks to complete
         #[arg(required = true, num_args = 1..)]
-        path: Vec<usize>,
+        indices: Vec<usize>,
+        /// for recurring tasks, replace the task with its next occurrence
+        /// instead of keeping the completed one
+        #[arg(long = "no-history")]
+        no_history: bool,
     },
     /// mark an item as incomplete
     #[command(visible_alias = "uc")]
     Uncheck {
         /// the nested index path of the task to mark as incomplete
-        #[arg(required = true, num_args = 1..)]
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
         path: Vec<usize>,
     },
     /// delete a task
     #[command(visible_alias = "d", visible_alias = "rm")]
     Delete {
-        /// the nested index path of the task to delete
-        #[arg(required = true, num_args = 1..)]
+        /// the nested index path of the task to delete, or a range of root
+        /// items such as 2..5 (both ends included)
+        #[arg(required_unless_present = "id", num_args = 1..)]
+        path: Vec<String>,
+        /// delete the task with this id, wherever it is
+        #[arg(long = "id", conflicts_with = "path")]
+        id: Option<String>,
+    },
+    /// copy a task (with its subtasks) right after the original
+    #[command(visible_alias = "dup")]
+    Duplicate {
+        /// the nested index path of the task to copy
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
         path: Vec<usize>,
     },
     /// clear all completed tasks
     #[command(visible_alias = "cl")]
     Clear,
+    /// remove completed tasks that have no subtasks, keeping parents
+    PruneEmpty,
+    /// move completed tasks into the project's archive
+    #[command(visible_alias = "ar")]
+    Archive {
+        /// show the archived tasks instead
+        #[arg(short = 'l', long = "list", conflicts_with = "restore")]
+        list: bool,
+        /// move the archived task at this index back to the task list
+        #[arg(short = 'r', long = "restore")]
+        restore: Option<usize>,
+    },
     /// clear all tasks
     #[command(visible_alias = "ca")]
     ClearAll,
+    /// undo the last change (repeat to go further back)
+    Undo,
+    /// redo the last undone change
+    Redo,
+    /// report problems in the tasks file without changing it
+    CheckData,
     /// move a task up or down in the list
     #[command(visible_alias = "m")]
     Move {
         /// the nested index path of the task to move
-        #[arg(required = true, num_args = 1..)]
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
         path: Vec<usize>,
         /// move up one position
         #[arg(short = 'u', long = "up")]
@@ -68,32 +373,154 @@ pub enum Commands {
         /// specific position to move to
         #[arg(short = 'p', long = "position")]
         position: Option<usize>,
+        /// move just before the sibling at this path
+        #[arg(
+            long = "before",
+            num_args = 1..,
+            value_delimiter = '.',
+            conflicts_with_all = ["up", "down", "top", "bottom", "position", "after", "into_before"]
+        )]
+        before: Option<Vec<usize>>,
+        /// move just after the sibling at this path
+        #[arg(
+            long = "after",
+            num_args = 1..,
+            value_delimiter = '.',
+            conflicts_with_all = ["up", "down", "top", "bottom", "position", "into_before"]
+        )]
+        after: Option<Vec<usize>>,
+        /// move just before the task at this path, under any parent
+        #[arg(
+            long = "into-before",
+            value_name = "PATH",
+            num_args = 1..,
+            value_delimiter = '.',
+            conflicts_with_all = ["up", "down", "top", "bottom", "position"]
+        )]
+        into_before: Option<Vec<usize>>,
+    },
+    /// move a task (with its subtasks) under a different parent
+    #[command(visible_alias = "rep")]
+    Reparent {
+        /// the nested index path of the task to move
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// nested index path of the new parent (leave empty for root level)
+        #[arg(long = "to", required = true, num_args = 0.., value_delimiter = '.')]
+        to: Option<Vec<usize>>,
+    },
+    /// turn a subtask into a root task, keeping its subtasks
+    Promote {
+        /// the nested index path of the subtask to promote
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+    },
+    /// move a task (with its subtasks) to another project
+    #[command(visible_alias = "mt")]
+    MoveTask {
+        /// the nested index path of the task to move
+        #[arg(required = true, num_args = 1.., value_delimiter = '.')]
+        path: Vec<usize>,
+        /// name of the project to move the task to
+        #[arg(long = "to")]
+        to: String,
+    },
+    /// export a project to a file or stdout
+    Export {
+        /// output format
+        #[arg(short = 'f', long = "format", value_enum, default_value = "markdown")]
+        format: ExportFormat,
+        /// file to write to (defaults to stdout)
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// import projects from a JSON export
+    Import {
+        /// JSON file created by `tm export --format json`
+        input: PathBuf,
+        /// append to existing projects instead of replacing all data
+        #[arg(short = 'm', long = "merge")]
+        merge: bool,
+    },
+    /// add tasks from a text file, one per line, indented for subtasks
+    ImportText {
+        /// text file; two spaces or a tab per nesting level, `[x]` marks done
+        input: PathBuf,
     },
     /// create a new project
     #[command(visible_alias = "cp")]
     CreateProject {
         /// name of the project to create
         name: String,
+        /// color to show the project name in, e.g. blue or magenta
+        #[arg(long = "color", value_parser = parse_color)]
+        color: Option<String>,
+    },
+    /// set the color a project's name is shown in
+    SetProjectColor {
+        /// name of the project
+        name: String,
+        /// color name, e.g. blue or magenta (omit to go back to the default)
+        #[arg(value_parser = parse_color)]
+        color: Option<String>,
     },
     /// switch to a different project
     #[command(visible_alias = "sp")]
     SwitchProject {
         /// name of the project to switch to
         name: String,
+        /// switch even while focused on another project, ending the focus
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// make every command target one project until `tm unfocus`
+    Focus {
+        /// name of the project to focus on
+        name: String,
+    },
+    /// end the focus started by `tm focus`
+    Unfocus,
+    /// rename an existing project
+    #[command(visible_alias = "rp")]
+    RenameProject {
+        /// current name of the project
+        old: String,
+        /// new name for the project
+        new: String,
     },
     /// list all available projects
     #[command(visible_alias = "lp")]
-    ListProjects,
+    ListProjects {
+        /// order the projects instead of listing them in creation order
+        #[arg(long = "sort", value_enum)]
+        sort: Option<ProjectSortKey>,
+    },
     /// delete a project
     #[command(visible_alias = "dp")]
     DeleteProject {
         /// name of the project to delete
         name: String,
+        /// project to switch to if the deleted one is current (default: "default")
+        #[arg(long = "switch-to")]
+        switch_to: Option<String>,
     },
     /// update TM CLI to the latest version
     Update,
     /// print version information
     Version,
+    /// show which data file is in use and what it holds
+    Info,
+    /// read or change defaults stored in config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// print a shell completion script
+    Completions {
+        /// shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
     /// completely remove TM CLI and all its data
     Uninstall {
         /// skip confirmation prompt
@@ -101,3 +528,89 @@ pub enum Commands {
         yes: bool,
     },
 }
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// print the value of a key
+    Get {
+        /// one of default_sort, color, compact, default_project, auto_archive_days
+        key: String,
+    },
+    /// set a key, or unset it with an empty value
+    Set {
+        /// one of default_sort, color, compact, default_project, auto_archive_days
+        key: String,
+        value: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// ascending priority number, unprioritized tasks last
+    Priority,
+    /// oldest first
+    Created,
+    /// alphabetically, ignoring case
+    Text,
+    /// earliest completed first, open tasks last
+    Completed,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProjectSortKey {
+    /// alphabetically, ignoring case
+    Name,
+    /// oldest first
+    Created,
+    /// most open tasks first
+    Tasks,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StatsFormat {
+    /// aligned text for reading in a terminal
+    Table,
+    /// plain JSON without colors, e.g. for piping to jq
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Markdown document with a nested checklist
+    Markdown,
+    /// every project as JSON, for backups (ignores --project)
+    Json,
+    /// one row per task, for spreadsheets (all projects unless --project is set)
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TimesFormat {
+    /// e.g. "2d ago"
+    Relative,
+    /// e.g. "2025-01-31 14:05"
+    Absolute,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn note_takes_the_path_before_the_text() {
+        let cli = Cli::try_parse_from(["tm", "note", "0", "1", "check expiry"]).unwrap();
+        let Commands::Note { path, text, clear } = cli.command else {
+            panic!("expected note");
+        };
+        assert_eq!(path, [0, 1]);
+        assert_eq!(text.as_deref(), Some("check expiry"));
+        assert_eq!(clear, None);
+    }
+}
@@ -0,0 +1,158 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::commands::SortKey;
+use crate::error::TmError;
+use crate::utils::{get_data_directory, validate_project_name};
+
+pub const KEYS: [&str; 5] = [
+    "default_sort",
+    "color",
+    "compact",
+    "default_project",
+    "auto_archive_days",
+];
+
+/// Defaults read from `config.toml` in the data directory. Command-line flags
+/// and environment variables take precedence over everything here.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// sort order for `list` when `--sort` isn't given
+    pub default_sort: Option<SortKey>,
+    /// false turns colored output off everywhere
+    pub color: bool,
+    /// write compact JSON, unless TM_COMPACT says otherwise
+    pub compact: bool,
+    /// project a new tasks file starts in
+    pub default_project: Option<String>,
+    /// archive tasks completed more than this many days ago on every run
+    pub auto_archive_days: Option<u32>,
+    /// project every command targets until `tm unfocus`; set by `tm focus`
+    /// rather than `config set`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_sort: None,
+            color: true,
+            compact: false,
+            default_project: None,
+            auto_archive_days: None,
+            focus: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf, TmError> {
+        Ok(get_data_directory()?.join("config.toml"))
+    }
+
+    /// Reads the config file, falling back to the defaults when there is
+    /// none (or no data directory to look in).
+    pub fn load() -> Result<Self, TmError> {
+        let path = match Self::path() {
+            Ok(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content)
+            .map_err(|e| TmError::InvalidConfig(format!("{}: {}", path.display(), e.message())))
+    }
+
+    /// Like `load`, but skips settings that can't be read instead of failing,
+    /// so a malformed file doesn't stop `tm config set` from fixing it.
+    pub fn load_valid() -> Self {
+        let mut config = Self::default();
+        let table = Self::path()
+            .and_then(|path| Ok(fs::read_to_string(path)?))
+            .ok()
+            .and_then(|content| content.parse::<toml::Table>().ok());
+        for (key, value) in table.unwrap_or_default() {
+            let value = match value {
+                toml::Value::String(value) => value,
+                other => other.to_string(),
+            };
+            if key == "focus" {
+                config.focus = Some(value);
+            } else {
+                let _ = config.set(&key, &value);
+            }
+        }
+        config
+    }
+
+    pub fn save(&self) -> Result<(), TmError> {
+        let content =
+            toml::to_string_pretty(self).map_err(|e| TmError::InvalidConfig(e.to_string()))?;
+        fs::write(Self::path()?, content)?;
+        Ok(())
+    }
+
+    /// Returns the value of `key` as it would be passed to `set`; unset keys
+    /// give an empty string.
+    pub fn get(&self, key: &str) -> Result<String, TmError> {
+        Ok(match key {
+            "default_sort" => self
+                .default_sort
+                .and_then(|sort| sort.to_possible_value())
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default(),
+            "color" => self.color.to_string(),
+            "compact" => self.compact.to_string(),
+            "default_project" => self.default_project.clone().unwrap_or_default(),
+            "auto_archive_days" => self
+                .auto_archive_days
+                .map(|days| days.to_string())
+                .unwrap_or_default(),
+            _ => return Err(Self::unknown_key(key)),
+        })
+    }
+
+    /// Sets `key` from its command-line form. An empty value unsets the
+    /// optional keys.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), TmError> {
+        let invalid = |expected: &str| {
+            TmError::InvalidConfig(format!(
+                "invalid value '{}' for {}, expected {}",
+                value, key, expected
+            ))
+        };
+        match key {
+            "default_sort" if value.is_empty() => self.default_sort = None,
+            "default_sort" => {
+                let sort = SortKey::from_str(value, true)
+                    .map_err(|_| invalid("priority, created, text or completed"))?;
+                self.default_sort = Some(sort);
+            }
+            "color" => self.color = value.parse().map_err(|_| invalid("true or false"))?,
+            "compact" => self.compact = value.parse().map_err(|_| invalid("true or false"))?,
+            "default_project" if value.is_empty() => self.default_project = None,
+            "default_project" => {
+                validate_project_name(value).map_err(TmError::InvalidConfig)?;
+                self.default_project = Some(value.to_string());
+            }
+            "auto_archive_days" if value.is_empty() => self.auto_archive_days = None,
+            "auto_archive_days" => {
+                let days = value.parse().map_err(|_| invalid("a number of days"))?;
+                self.auto_archive_days = Some(days);
+            }
+            _ => return Err(Self::unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    fn unknown_key(key: &str) -> TmError {
+        TmError::InvalidConfig(format!(
+            "unknown config key '{}', expected one of: {}",
+            key,
+            KEYS.join(", ")
+        ))
+    }
+}
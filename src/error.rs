@@ -0,0 +1,87 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::utils::format_path;
+
+#[derive(Debug)]
+pub enum TmError {
+    Io(io::Error),
+    Serde(serde_json::Error),
+    Csv(csv::Error),
+    ProjectNotFound(String),
+    PathNotFound(Vec<usize>),
+    // The unreadable file was moved aside; `backup_valid` tells whether the
+    // .bak next to it can be restored
+    CorruptData {
+        reason: String,
+        moved_to: PathBuf,
+        backup_valid: bool,
+    },
+    DataDirUnavailable,
+    Locked,
+    InvalidConfig(String),
+    InvalidProjectName(String),
+    TooDeep(usize),
+    EmptyText,
+}
+
+impl fmt::Display for TmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TmError::Io(e) => write!(f, "{}", e),
+            TmError::Serde(e) => write!(f, "{}", e),
+            TmError::Csv(e) => write!(f, "{}", e),
+            TmError::ProjectNotFound(name) => write!(f, "project '{}' not found", name),
+            TmError::PathNotFound(path) => {
+                write!(f, "item at path {} not found", format_path(path))
+            }
+            TmError::CorruptData {
+                reason, moved_to, ..
+            } => write!(
+                f,
+                "tasks file is not valid ({}), moved it to {}",
+                reason,
+                moved_to.display()
+            ),
+            TmError::DataDirUnavailable => write!(
+                f,
+                "could not determine data directory, set TM_DATA_DIR to choose one"
+            ),
+            TmError::Locked => write!(f, "another tm process is running, try again shortly"),
+            TmError::InvalidConfig(reason) => write!(f, "{}", reason),
+            TmError::InvalidProjectName(reason) => write!(f, "{}", reason),
+            TmError::TooDeep(max) => write!(f, "tasks can be nested at most {} levels deep", max),
+            TmError::EmptyText => write!(f, "task text can't be empty"),
+        }
+    }
+}
+
+impl std::error::Error for TmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TmError::Io(e) => Some(e),
+            TmError::Serde(e) => Some(e),
+            TmError::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TmError {
+    fn from(e: io::Error) -> Self {
+        TmError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TmError {
+    fn from(e: serde_json::Error) -> Self {
+        TmError::Serde(e)
+    }
+}
+
+impl From<csv::Error> for TmError {
+    fn from(e: csv::Error) -> Self {
+        TmError::Csv(e)
+    }
+}
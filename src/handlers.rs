@@ -1,23 +1,66 @@
+use chrono::{DateTime, Local, Utc};
+use clap::CommandFactory;
+use clap_complete::Shell;
 use colored::Colorize;
+use semver::Version;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::fs;
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
 use std::process::Command;
 
-use crate::store::TaskStore;
-use crate::utils::{format_path, get_data_directory};
+use crate::commands::{Cli, ConfigAction, ExportFormat, ProjectSortKey, StatsFormat};
+use crate::config::Config;
+use crate::error::TmError;
+use crate::models::{new_task_id, ProjectStats, Task};
+use crate::store::{ListOptions, TaskStore};
+use crate::utils::{
+    display_width, find_url, format_path, get_data_directory, humanize, markers, pad, paint_project,
+};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const INSTALL_SCRIPT_URL: &str = "https://tm-cli.com/install";
+const VERSION_API_URL: &str = "https://tm-cli.com/api/version";
+const RELEASES_URL: &str = "https://github.com/Inflect-Labs/tm/releases";
 
 pub fn handle_add(
     store: &mut TaskStore,
     path: Vec<usize>,
-    text: String,
+    under: Option<String>,
+    task: Task,
+    top: bool,
+    after: Option<usize>,
+    before: Option<usize>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.add_task(path.clone(), text)? {
+    let path = match under {
+        Some(parent) => resolve_text_path(store, &parent),
+        None => path,
+    };
+    let out_of_range = |index: usize| -> ! {
+        eprintln!("error: index {} is out of range", index);
+        std::process::exit(1);
+    };
+    let position = match (top, after, before) {
+        (true, _, _) => Some(0),
+        (_, Some(index), _) => Some(index.checked_add(1).unwrap_or_else(|| out_of_range(index))),
+        (_, _, before) => before,
+    };
+    let added = match store.add_task(path.clone(), task, position) {
+        Err(TmError::PathNotFound(_)) => out_of_range(after.or(before).unwrap_or_default()),
+        result => result?,
+    };
+    if added {
         if path.is_empty() {
             println!("added task item");
         } else {
             println!("added subtask to item {}", format_path(&path));
+            for reopened in store.reopen_ancestors(&path) {
+                println!(
+                    "reopened item {} since it now has an open subtask",
+                    format_path(&reopened)
+                );
+            }
         }
     } else {
         eprintln!(
@@ -29,24 +72,800 @@ pub fn handle_add(
     Ok(())
 }
 
-pub fn handle_list(store: &mut TaskStore) {
-    println!("");
+/// Adds every non-blank line of stdin as a root task, each a copy of
+/// `template` with the line as its text.
+pub fn handle_add_stdin(
+    store: &mut TaskStore,
+    template: Task,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut added = 0;
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let task = Task {
+            id: new_task_id(),
+            text: text.to_string(),
+            ..template.clone()
+        };
+        store.add_task(Vec::new(), task, None)?;
+        added += 1;
+    }
+    println!("added {} task item(s)", added);
+    Ok(())
+}
+
+/// Opens $EDITOR on a temp file and returns what was saved, trimmed. Exits
+/// without adding anything if the editor fails or the file is left empty.
+pub fn read_task_from_editor() -> Result<String, Box<dyn std::error::Error>> {
+    let default_editor = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = std::env::var("EDITOR")
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| default_editor.to_string());
+    // $EDITOR may carry arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(default_editor);
+
+    let temp_path = std::env::temp_dir().join(format!("tm-task-{}.txt", std::process::id()));
+    fs::write(&temp_path, "")?;
+    let status = Command::new(program).args(parts).arg(&temp_path).status();
+    let content = fs::read_to_string(&temp_path);
+    let _ = fs::remove_file(&temp_path);
+
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(_) => {
+            eprintln!("error: editor exited with an error, task not added");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: could not run editor '{}': {}", program, e);
+            std::process::exit(1);
+        }
+    }
+
+    let text = content?.trim_end().to_string();
+    if text.trim().is_empty() {
+        eprintln!("error: empty task text, task not added");
+        std::process::exit(1);
+    }
+    Ok(text)
+}
+
+pub fn handle_corrupt_data(
+    store: &mut TaskStore,
+    error: TmError,
+) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("error: {}", error);
+    let TmError::CorruptData { backup_valid, .. } = error else {
+        return Err(error.into());
+    };
+    if !backup_valid {
+        eprintln!("no usable backup found, starting with an empty task list");
+        return Ok(());
+    }
+
+    let backup = store.backup_path();
+    if !std::io::stdin().is_terminal() {
+        eprintln!("a valid backup exists at {}", backup.display());
+        eprintln!("run tm interactively to restore it, or copy it back by hand");
+        std::process::exit(1);
+    }
+
+    print!("Restore the last backup from {}? (y/N): ", backup.display());
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    if input.trim().to_lowercase().starts_with('y') {
+        store.restore_backup()?;
+        println!("restored backup");
+    } else {
+        println!("starting with an empty task list");
+    }
+    Ok(())
+}
+
+pub fn handle_list(
+    store: &mut TaskStore,
+    project: Option<String>,
+    all: bool,
+    json: bool,
+    plain: bool,
+    porcelain: bool,
+    options: ListOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current = store.get_current_project_name().to_string();
+    if all {
+        for name in store.project_names() {
+            let tasks = store.list_project_tasks(&name).unwrap();
+            if plain {
+                println!("# {}", name);
+                println!();
+                print!("{}", TaskStore::tasks_to_markdown(tasks));
+                println!();
+            } else {
+                let color = store.project_color(&name);
+                print_project(&name, color, name == current, tasks, &options);
+            }
+        }
+        return Ok(());
+    }
+
+    let name = project.unwrap_or_else(|| current.clone());
+    let tasks = match store.list_project_tasks(&name) {
+        Some(tasks) => tasks,
+        None => {
+            eprintln!("error: project '{}' not found", name);
+            std::process::exit(1);
+        }
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(tasks)?);
+        return Ok(());
+    }
+
+    if plain {
+        print!("{}", TaskStore::tasks_to_markdown(tasks));
+        return Ok(());
+    }
+
+    if porcelain {
+        print!("{}", TaskStore::tasks_to_porcelain(tasks));
+        return Ok(());
+    }
+
+    let color = store.project_color(&name);
+    print_project(&name, color, name == current, tasks, &options);
+    Ok(())
+}
+
+fn print_project(
+    name: &str,
+    color: Option<&str>,
+    is_current: bool,
+    tasks: &[Task],
+    options: &ListOptions,
+) {
+    println!();
+    if is_current {
+        println!("      Current: {}", paint_project(name, color));
+    } else {
+        println!("      Project: {}", paint_project(name, color));
+    }
+    if !tasks.is_empty() {
+        let stats = TaskStore::stats_for(tasks);
+        println!(
+            "      {} open {} {} done",
+            stats.open().to_string().red(),
+            markers().separator,
+            stats.completed.to_string().green()
+        );
+    }
+    println!();
+    TaskStore::print_task_list(tasks, options);
+    println!();
+    println!();
+}
+
+pub fn handle_rename(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    text: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if store.rename_task(path.clone(), text.clone())? {
+        println!("renamed item {} to '{}'", format_path(&path), text.trim());
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_split(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match store.split_task(path.clone())? {
+        Some(0) => {
+            eprintln!(
+                "error: item {} has a single line, nothing to split",
+                format_path(&path)
+            );
+            std::process::exit(1);
+        }
+        Some(added) => {
+            println!(
+                "split item {} into {} subtask(s)",
+                format_path(&path),
+                added
+            );
+            for reopened in store.reopen_ancestors(&path) {
+                println!(
+                    "reopened item {} since it now has an open subtask",
+                    format_path(&reopened)
+                );
+            }
+        }
+        None => {
+            eprintln!("error: item at path {} not found", format_path(&path));
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_set_priority(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    level: u8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if store.set_priority(path.clone(), Some(level))? {
+        println!("set priority of item {} to {}", format_path(&path), level);
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_note(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    note: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cleared = note.is_none();
+    if store.set_note(path.clone(), note)? {
+        if cleared {
+            println!("cleared note of item {}", format_path(&path));
+        } else {
+            println!("set note of item {}", format_path(&path));
+        }
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_tag(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    tags: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if store.add_tags(path.clone(), tags.clone())? {
+        println!("tagged item {} with {}", format_path(&path), tags.join(" "));
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_open(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let task = match store.find_item(path.clone()) {
+        Some(task) => task,
+        None => {
+            eprintln!("error: item at path {} not found", format_path(&path));
+            std::process::exit(1);
+        }
+    };
+    let url = match find_url(&task.text) {
+        Some(url) => url.to_string(),
+        None => {
+            eprintln!("error: item {} has no http(s) link", format_path(&path));
+            std::process::exit(1);
+        }
+    };
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // The empty argument is the window title `start` expects first
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    match command.arg(&url).status() {
+        Ok(status) if status.success() => println!("opened {}", url),
+        Ok(_) | Err(_) => {
+            eprintln!("error: could not open {} in a browser", url);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_show(store: &mut TaskStore, path: Vec<usize>) {
+    let task = match store.find_item(path.clone()) {
+        Some(task) => task,
+        None => {
+            eprintln!("error: item at path {} not found", format_path(&path));
+            std::process::exit(1);
+        }
+    };
+
+    let status = if task.completed {
+        "completed".green()
+    } else {
+        "open".red()
+    };
+
+    println!();
+    println!("      {}.  {}", format_path(&path), task.text.bold());
+    println!();
+    println!("      status:     {}", status);
+    println!("      id:         {}", task.id.dimmed());
+    if let Some(priority) = task.priority {
+        println!("      priority:   {}", priority);
+    }
+    if let Some(due) = task.due {
+        println!("      due:        {}", due.format("%Y-%m-%d"));
+    }
+    if let Some(recurrence) = task.recurrence {
+        println!("      repeats:    {}", recurrence.as_str());
+    }
+    println!(
+        "      created:    {} {}",
+        task.created_at.format("%Y-%m-%d %H:%M"),
+        format!("({})", humanize(task.created_at)).dimmed()
+    );
+    if let Some(completed_at) = task.completed_at {
+        println!(
+            "      completed:  {} {}",
+            completed_at.format("%Y-%m-%d %H:%M"),
+            format!("({})", humanize(completed_at)).dimmed()
+        );
+    }
+    if let Some(comment) = &task.completion_note {
+        println!("      resolution: {}", comment);
+    }
+    if !task.tags.is_empty() {
+        println!("      tags:       {}", task.tags.join(" ").cyan());
+    }
+    println!("      subtasks:   {}", task.subtasks.len());
+    if let Some(note) = &task.note {
+        println!();
+        for line in note.lines() {
+            println!("      {}", line);
+        }
+    }
+    println!();
+}
+
+pub fn handle_search(store: &mut TaskStore, query: String, all_projects: bool, count: bool) {
+    if all_projects {
+        let matches = store.search_all_projects(&query);
+        if count {
+            println!("{}", matches.len());
+            return;
+        }
+        if matches.is_empty() {
+            println!("no tasks matching '{}'", query);
+        }
+        for (project, path, text) in matches {
+            println!("{}  {}  {}", project.green(), format_path(&path), text);
+        }
+    } else {
+        let matches = store.search(&query);
+        if count {
+            println!("{}", matches.len());
+            return;
+        }
+        if matches.is_empty() {
+            println!("no tasks matching '{}'", query);
+        }
+        for (path, text) in matches {
+            println!("{}  {}", format_path(&path), text);
+        }
+    }
+}
+
+pub fn handle_next(store: &mut TaskStore, deep: bool) {
+    match store.next_task(deep) {
+        Some((path, task)) => println!("{}  {}", format_path(&path), task.text),
+        None => {
+            eprintln!("no open tasks");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn handle_log(store: &mut TaskStore, limit: usize, all_projects: bool) {
+    let entries = store.completed_log(all_projects);
+    if entries.is_empty() {
+        println!("no completed tasks yet");
+        return;
+    }
+    for entry in entries.into_iter().take(limit) {
+        let when = format!("{:>8}", humanize(entry.completed_at));
+        let note = entry
+            .note
+            .map(|note| {
+                format!("  {} {}", markers().dash, note)
+                    .dimmed()
+                    .to_string()
+            })
+            .unwrap_or_default();
+        if all_projects {
+            println!(
+                "{}  {}  {}  {}{}",
+                when.dimmed(),
+                entry.project.green(),
+                format_path(&entry.path),
+                entry.text,
+                note
+            );
+        } else {
+            println!(
+                "{}  {}  {}{}",
+                when.dimmed(),
+                format_path(&entry.path),
+                entry.text,
+                note
+            );
+        }
+    }
+}
+
+pub fn handle_today(store: &mut TaskStore, overdue: bool) {
+    let groups = store.today(overdue);
+    if groups.is_empty() {
+        println!("nothing due or created today");
+        return;
+    }
+    let today = Local::now().date_naive();
+    for (project, tasks) in groups {
+        println!();
+        println!("      {}", project.green());
+        println!();
+        for (path, task) in tasks {
+            let status = if task.completed {
+                markers().done.green()
+            } else {
+                markers().open.red()
+            };
+            let mut line = format!("      [{}]  {}  {}", status, format_path(&path), task.text);
+            if let Some(due) = task.due {
+                let due_label = format!("(due {})", due.format("%Y-%m-%d"));
+                if !task.completed && due.date_naive() < today {
+                    line.push_str(&format!("  {}", due_label.red()));
+                } else {
+                    line.push_str(&format!("  {}", due_label.dimmed()));
+                }
+            }
+            println!("{}", line);
+        }
+    }
+    println!();
+}
+
+/// Prints one tab-separated line per open task due today or earlier:
+/// due date, project, path and text.
+pub fn handle_remind(store: &TaskStore, notify: bool, bell: bool) {
+    let mut texts = Vec::new();
+    for (project, tasks) in store.due_open() {
+        for (path, task) in tasks {
+            let due = task.due.map(|due| due.format("%Y-%m-%d").to_string());
+            println!(
+                "{}\t{}\t{}\t{}",
+                due.unwrap_or_default(),
+                project,
+                format_path(&path),
+                task.text
+            );
+            texts.push(task.text.clone());
+        }
+    }
+    if texts.is_empty() {
+        return;
+    }
+
+    if bell {
+        eprint!("\x07");
+    }
+    if notify {
+        let summary = format!("{} task(s) due", texts.len());
+        send_notification(&summary, &texts.join("\n"));
+    }
+}
+
+// Notifications are best-effort: a missing backend only earns a warning
+#[cfg(feature = "notify")]
+fn send_notification(summary: &str, body: &str) {
+    let result = notify_rust::Notification::new()
+        .appname("tm")
+        .summary(summary)
+        .body(body)
+        .show();
+    if let Err(e) = result {
+        eprintln!("warning: could not show notification: {}", e);
+    }
+}
+
+#[cfg(not(feature = "notify"))]
+fn send_notification(_summary: &str, _body: &str) {
+    eprintln!("warning: this tm was built without notifications, rebuild with --features notify");
+}
+
+fn resolve_text_path(store: &mut TaskStore, text: &str) -> Vec<usize> {
+    let matches = store.find_by_text(text);
+    match matches.len() {
+        0 => {
+            eprintln!("error: no task with text '{}' found", text);
+            std::process::exit(1);
+        }
+        1 => matches.into_iter().next().unwrap(),
+        _ => {
+            eprintln!("error: text '{}' matches multiple tasks:", text);
+            for path in &matches {
+                eprintln!("  {}", format_path(path));
+            }
+            eprintln!("use the index path to pick one");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn resolve_id_path(store: &mut TaskStore, id: &str) -> Vec<usize> {
+    match store.find_by_id(id) {
+        Some(path) => path,
+        None => {
+            eprintln!(
+                "error: no task with id '{}' in project '{}'",
+                id,
+                store.get_current_project_name()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_completed_since(store: &mut TaskStore, all_projects: bool, since: DateTime<Utc>) {
+    let date = since.format("%Y-%m-%d");
+    let skipped = if all_projects {
+        let counts = store.all_completed_since(since);
+        let width = counts
+            .iter()
+            .map(|(name, _)| display_width(name))
+            .max()
+            .unwrap_or(0)
+            .max("project".len());
+
+        println!("{:<width$}  {:>6}", "project", "done");
+        for (name, (done, _)) in &counts {
+            println!("{}  {:>6}", pad(name, width), done);
+        }
+        counts.iter().map(|(_, (_, skipped))| skipped).sum()
+    } else {
+        let (done, skipped) = store.completed_since(since);
+        println!();
+        println!(
+            "      Current: {}",
+            store.get_current_project_name().green()
+        );
+        println!();
+        println!(
+            "      completed since {}:  {}",
+            date,
+            done.to_string().green()
+        );
+        println!();
+        skipped
+    };
+
+    if skipped > 0 {
+        println!(
+            "{}",
+            format!(
+                "note: {} completed tasks have no completion time and were not counted",
+                skipped
+            )
+            .dimmed()
+        );
+    }
+}
+
+#[derive(Serialize)]
+struct StatsEntry<'a> {
+    project: &'a str,
+    #[serde(flatten)]
+    stats: &'a ProjectStats,
+}
+
+pub fn handle_stats(
+    store: &mut TaskStore,
+    all_projects: bool,
+    since: Option<DateTime<Utc>>,
+    format: StatsFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(since) = since {
+        print_completed_since(store, all_projects, since);
+        return Ok(());
+    }
+
+    if let StatsFormat::Json = format {
+        // One object for the current project, an array with --all-projects
+        let json = if all_projects {
+            let all_stats = store.all_project_stats();
+            let entries: Vec<StatsEntry> = all_stats
+                .iter()
+                .map(|(name, stats)| StatsEntry {
+                    project: name,
+                    stats,
+                })
+                .collect();
+            serde_json::to_string_pretty(&entries)?
+        } else {
+            let stats = store.stats();
+            serde_json::to_string_pretty(&StatsEntry {
+                project: store.get_current_project_name(),
+                stats: &stats,
+            })?
+        };
+        println!("{}", json);
+        return Ok(());
+    }
+
+    if all_projects {
+        let all_stats = store.all_project_stats();
+        let width = all_stats
+            .iter()
+            .map(|(name, _)| display_width(name))
+            .max()
+            .unwrap_or(0)
+            .max("project".len());
+
+        println!(
+            "{:<width$}  {:>6}  {:>6}  {:>6}  {:>6}",
+            "project", "total", "done", "open", "done%"
+        );
+        for (name, stats) in all_stats {
+            println!(
+                "{}  {:>6}  {:>6}  {:>6}  {:>5.0}%",
+                pad(&name, width),
+                stats.total,
+                stats.completed,
+                stats.open(),
+                stats.completion_pct()
+            );
+        }
+        return Ok(());
+    }
+
+    let stats = store.stats();
+    println!();
     println!(
         "      Current: {}",
         store.get_current_project_name().green()
     );
-    println!("");
-    store.list_tasks();
-    println!("");
-    println!("");
+    println!();
+    println!("      total:      {}", stats.total);
+    println!(
+        "      completed:  {} ({:.0}%)",
+        stats.completed.to_string().green(),
+        stats.completion_pct()
+    );
+    println!("      open:       {}", stats.open().to_string().red());
+    if let Some(oldest) = stats.oldest_open {
+        println!(
+            "      oldest open task: {} days old",
+            (Utc::now() - oldest).num_days()
+        );
+    }
+    println!();
+    Ok(())
+}
+
+pub fn handle_count(store: &mut TaskStore, all: bool, recursive: bool) {
+    println!("{}", store.count(all, recursive));
+}
+
+pub fn handle_pending(store: &mut TaskStore, verbose: bool) {
+    let open = store.count(false, true);
+    if verbose {
+        println!("{} open tasks", open);
+    }
+    if open > 0 {
+        std::process::exit(1);
+    }
 }
 
 pub fn handle_check(
     store: &mut TaskStore,
     path: Vec<usize>,
+    text: Option<String>,
+    keep_history: bool,
+    comment: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = match text {
+        Some(text) => resolve_text_path(store, &text),
+        None => path,
+    };
+    if store.complete_task(path.clone(), keep_history, comment)? {
+        println!("completed item {}", format_path(&path));
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_check_many(
+    store: &mut TaskStore,
+    indices: Vec<usize>,
+    keep_history: bool,
+    comment: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let found = store.complete_many(&indices, keep_history, comment)?;
+    for (index, found) in indices.iter().zip(&found) {
+        if *found {
+            println!("completed item {}", index);
+        } else {
+            eprintln!("error: item at path {} not found", index);
+        }
+    }
+    if found.contains(&false) {
+        // Exiting skips the flush in main, so keep the tasks that were found
+        store.flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_check_all(
+    store: &mut TaskStore,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = store.get_current_project_name().to_string();
+    if !yes {
+        print!(
+            "Mark every task in project '{}' as completed? (y/N): ",
+            name
+        );
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+
+        if !input.trim().to_lowercase().starts_with('y') {
+            println!("Nothing completed.");
+            return Ok(());
+        }
+    }
+
+    let count = store.complete_all()?;
+    println!("completed {} item(s) in project '{}'", count, name);
+    Ok(())
+}
+
+pub fn handle_uncheck(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if store.uncomplete_task(path.clone())? {
+        println!("uncompleted item {}", format_path(&path));
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_delete(
+    store: &mut TaskStore,
+    path: Vec<usize>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.complete_task(path.clone())? {
-        println!("completed item {}", format_path(&path));
+    if store.delete_task(path.clone())? {
+        println!("deleted item {}", format_path(&path));
     } else {
         eprintln!("error: item at path {} not found", format_path(&path));
         std::process::exit(1);
@@ -54,25 +873,32 @@ pub fn handle_check(
     Ok(())
 }
 
-pub fn handle_uncheck(
+pub fn handle_delete_many(
     store: &mut TaskStore,
-    path: Vec<usize>,
+    indices: Vec<usize>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.uncomplete_task(path.clone())? {
-        println!("uncompleted item {}", format_path(&path));
-    } else {
-        eprintln!("error: item at path {} not found", format_path(&path));
+    let found = store.delete_many(&indices)?;
+    for (index, found) in indices.iter().zip(&found) {
+        if *found {
+            println!("deleted item {}", index);
+        } else {
+            eprintln!("error: item at path {} not found", index);
+        }
+    }
+    if found.contains(&false) {
+        // Exiting skips the flush in main, so keep the deletions that worked
+        store.flush()?;
         std::process::exit(1);
     }
     Ok(())
 }
 
-pub fn handle_delete(
+pub fn handle_duplicate(
     store: &mut TaskStore,
     path: Vec<usize>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.delete_task(path.clone())? {
-        println!("deleted item {}", format_path(&path));
+    if store.duplicate_task(path.clone())? {
+        println!("duplicated item {}", format_path(&path));
     } else {
         eprintln!("error: item at path {} not found", format_path(&path));
         std::process::exit(1);
@@ -82,43 +908,152 @@ pub fn handle_delete(
 
 pub fn handle_clear(store: &mut TaskStore) -> Result<(), Box<dyn std::error::Error>> {
     store.clear_completed()?;
-    println!("cleared completed items");
+    println!(
+        "cleared completed items from project '{}'",
+        store.get_current_project_name()
+    );
+    Ok(())
+}
+
+pub fn handle_prune_empty(store: &mut TaskStore) -> Result<(), Box<dyn std::error::Error>> {
+    let removed = store.prune_completed()?;
+    println!(
+        "removed {} completed item(s) without subtasks from project '{}'",
+        removed,
+        store.get_current_project_name()
+    );
+    Ok(())
+}
+
+pub fn handle_archive(
+    store: &mut TaskStore,
+    list: bool,
+    restore: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if list {
+        println!();
+        println!(
+            "      Archive: {}",
+            store.get_current_project_name().green()
+        );
+        println!();
+        TaskStore::print_task_list(store.archived_tasks(), &ListOptions::default());
+        println!();
+        println!();
+    } else if let Some(index) = restore {
+        if store.restore_archived(index)? {
+            println!("restored archived item {}", index);
+        } else {
+            eprintln!("error: archived item {} not found", index);
+            std::process::exit(1);
+        }
+    } else {
+        let count = store.archive_completed()?;
+        println!("archived {} completed items", count);
+    }
     Ok(())
 }
 
 pub fn handle_clear_all(store: &mut TaskStore) -> Result<(), Box<dyn std::error::Error>> {
     store.clear_all()?;
-    println!("cleared all items");
+    println!(
+        "cleared all items from project '{}'",
+        store.get_current_project_name()
+    );
     Ok(())
 }
 
-pub fn handle_move(
-    store: &mut TaskStore,
-    path: Vec<usize>,
+pub fn handle_undo(store: &mut TaskStore) -> Result<(), Box<dyn std::error::Error>> {
+    if store.undo()? {
+        println!("undid last change");
+    } else {
+        eprintln!("error: nothing to undo");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_redo(store: &mut TaskStore) -> Result<(), Box<dyn std::error::Error>> {
+    if store.redo()? {
+        println!("redid last undone change");
+    } else {
+        eprintln!("error: nothing to redo");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_check_data(store: &TaskStore) -> Result<(), Box<dyn std::error::Error>> {
+    let issues = store.check_data()?;
+    if issues.is_empty() {
+        println!("no problems found");
+        return Ok(());
+    }
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    eprintln!("error: found {} problem(s)", issues.len());
+    std::process::exit(1);
+}
+
+/// Turns the `move` flags into the direction `move_task` takes, requiring
+/// exactly one of them.
+fn direction(
     up: bool,
     down: bool,
     top: bool,
     bottom: bool,
     position: Option<usize>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Determine the direction based on the flags
-    let direction = if up {
-        "up".to_string()
+) -> Result<String, String> {
+    let flags_set = [up, down, top, bottom, position.is_some()]
+        .iter()
+        .filter(|&&set| set)
+        .count();
+    if flags_set > 1 {
+        return Err("specify exactly one direction (-u, -d, -t, -b or -p)".to_string());
+    }
+    if up {
+        Ok("up".to_string())
     } else if down {
-        "down".to_string()
+        Ok("down".to_string())
     } else if top {
-        "top".to_string()
+        Ok("top".to_string())
     } else if bottom {
-        "bottom".to_string()
+        Ok("bottom".to_string())
     } else if let Some(pos) = position {
-        pos.to_string()
+        Ok(pos.to_string())
     } else {
-        eprintln!("error: must specify a direction flag (-u, -d, -t, -b) or position (-p)");
-        std::process::exit(1);
+        Err(
+            "must specify a direction flag (-u, -d, -t, -b), position (-p), --before, --after or --into-before"
+                .to_string(),
+        )
+    }
+}
+
+pub fn handle_move(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    up: bool,
+    down: bool,
+    top: bool,
+    bottom: bool,
+    position: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let direction = match direction(up, down, top, bottom, position) {
+        Ok(direction) => direction,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            std::process::exit(1);
+        }
     };
 
     if store.move_task(path.clone(), &direction)? {
-        println!("moved item {} {}", format_path(&path), direction);
+        match position {
+            Some(position) => {
+                println!("moved item {} to position {}", format_path(&path), position)
+            }
+            None => println!("moved item {} {}", format_path(&path), direction),
+        }
     } else {
         eprintln!("error: could not move item at path {}", format_path(&path));
         std::process::exit(1);
@@ -126,11 +1061,196 @@ pub fn handle_move(
     Ok(())
 }
 
+pub fn handle_promote(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if path.len() < 2 {
+        eprintln!("error: item {} is already a root task", format_path(&path));
+        std::process::exit(1);
+    }
+
+    if store.promote(path.clone())? {
+        let root = store.get_current_tasks().len() - 1;
+        println!("promoted item {} to root item {}", format_path(&path), root);
+    } else {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_reparent(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    to: Vec<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if to.starts_with(&path) {
+        eprintln!(
+            "error: cannot move item {} under itself or one of its subtasks",
+            format_path(&path)
+        );
+        std::process::exit(1);
+    }
+
+    if store.reparent(path.clone(), to.clone())? {
+        if to.is_empty() {
+            println!("moved item {} to root level", format_path(&path));
+        } else {
+            println!(
+                "moved item {} under item {}",
+                format_path(&path),
+                format_path(&to)
+            );
+        }
+    } else if to.is_empty() {
+        eprintln!("error: item at path {} not found", format_path(&path));
+        std::process::exit(1);
+    } else {
+        eprintln!(
+            "error: item at path {} or new parent at path {} not found",
+            format_path(&path),
+            format_path(&to)
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_move_relative(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    target: Vec<usize>,
+    after: bool,
+    any_parent: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let side = if after { "after" } else { "before" };
+    // --before and --after only reorder; --into-before may also reparent
+    if !any_parent
+        && path.split_last().map(|(_, parent)| parent)
+            != target.split_last().map(|(_, parent)| parent)
+    {
+        eprintln!(
+            "error: item {} is not a sibling of item {}, use --into-before to move it under another parent",
+            format_path(&target),
+            format_path(&path)
+        );
+        std::process::exit(1);
+    }
+    if store.move_next_to(path.clone(), target.clone(), after)? {
+        println!(
+            "moved item {} {} item {}",
+            format_path(&path),
+            side,
+            format_path(&target)
+        );
+    } else {
+        eprintln!(
+            "error: could not move item at path {} {} item {}",
+            format_path(&path),
+            side,
+            format_path(&target)
+        );
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn handle_move_task(
+    store: &mut TaskStore,
+    path: Vec<usize>,
+    to: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match store.move_task_to_project(path.clone(), to.clone()) {
+        Ok(()) => println!("moved item {} to project '{}'", format_path(&path), to),
+        Err(e @ (TmError::ProjectNotFound(_) | TmError::PathNotFound(_))) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+pub fn handle_export(
+    store: &mut TaskStore,
+    format: ExportFormat,
+    output: Option<PathBuf>,
+    project: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = project
+        .clone()
+        .unwrap_or_else(|| store.get_current_project_name().to_string());
+    let content = match format {
+        ExportFormat::Markdown => store.export_markdown(&name),
+        ExportFormat::Json => Some(store.export_json()?),
+        ExportFormat::Csv => store.export_csv(project.as_deref())?,
+    };
+
+    let content = match content {
+        Some(content) => content,
+        None => {
+            eprintln!("error: project '{}' not found", name);
+            std::process::exit(1);
+        }
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(&path, content)?;
+            match (format, project) {
+                (ExportFormat::Markdown, _) | (ExportFormat::Csv, Some(_)) => {
+                    println!("exported project '{}' to {}", name, path.display())
+                }
+                _ => println!("exported all projects to {}", path.display()),
+            }
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+pub fn handle_import(
+    store: &mut TaskStore,
+    input: PathBuf,
+    merge: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(&input)?;
+    match store.import_json(&content, merge) {
+        Ok(count) if merge => println!("merged {} projects from {}", count, input.display()),
+        Ok(count) => println!("imported {} projects from {}", count, input.display()),
+        Err(TmError::Serde(e)) => {
+            eprintln!("error: {} is not a valid tm export: {}", input.display(), e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+pub fn handle_import_text(
+    store: &mut TaskStore,
+    input: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(&input)?;
+    let count = store.import_text(&content)?;
+    println!(
+        "added {} tasks from {} to project '{}'",
+        count,
+        input.display(),
+        store.get_current_project_name()
+    );
+    Ok(())
+}
+
 pub fn handle_create_project(
     store: &mut TaskStore,
     name: String,
+    color: Option<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.create_project(name.clone())? {
+    // Blank and otherwise unusable names are rejected by the store
+    let name = name.trim().to_string();
+    if store.create_project(name.clone(), color)? {
         println!("created project '{}'", name);
     } else {
         eprintln!("error: project '{}' already exists", name);
@@ -139,12 +1259,48 @@ pub fn handle_create_project(
     Ok(())
 }
 
+pub fn handle_set_project_color(
+    store: &mut TaskStore,
+    name: String,
+    color: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match store.set_project_color(&name, color.clone()) {
+        Ok(()) => match color {
+            Some(color) => println!("project '{}' is now shown in {}", name, color),
+            None => println!("project '{}' is back to the default color", name),
+        },
+        Err(e @ TmError::ProjectNotFound(_)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
 pub fn handle_switch_project(
     store: &mut TaskStore,
+    mut config: Config,
     name: String,
+    force: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let focus = config.focus.take();
+    if let Some(focus) = &focus {
+        if !force {
+            eprintln!(
+                "error: focused on project '{}', run `tm unfocus` or pass --force to switch anyway",
+                focus
+            );
+            std::process::exit(1);
+        }
+    }
+
     if store.switch_project(name.clone())? {
         println!("switched to project '{}'", name);
+        if let Some(focus) = focus {
+            config.save()?;
+            println!("no longer focused on project '{}'", focus);
+        }
     } else {
         eprintln!("error: project '{}' not found", name);
         std::process::exit(1);
@@ -152,32 +1308,124 @@ pub fn handle_switch_project(
     Ok(())
 }
 
-pub fn handle_list_projects(store: &TaskStore) {
-    store.list_projects();
+pub fn handle_focus(
+    store: &TaskStore,
+    mut config: Config,
+    name: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !store.project_names().contains(&name) {
+        eprintln!("error: project '{}' not found", name);
+        std::process::exit(1);
+    }
+    config.focus = Some(name.clone());
+    config.save()?;
+    println!(
+        "focused on project '{}': every command targets it until `tm unfocus`",
+        name
+    );
+    Ok(())
+}
+
+pub fn handle_unfocus(mut config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    match config.focus.take() {
+        Some(name) => {
+            config.save()?;
+            println!("no longer focused on project '{}'", name);
+        }
+        None => println!("not focused on any project"),
+    }
+    Ok(())
+}
+
+pub fn handle_rename_project(
+    store: &mut TaskStore,
+    mut config: Config,
+    old: String,
+    new: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Blank and otherwise unusable names are rejected by the store
+    let new = new.trim().to_string();
+    match store.rename_project(old.clone(), new.clone()) {
+        Ok(true) => {
+            if config.focus.as_ref() == Some(&old) {
+                config.focus = Some(new.clone());
+                config.save()?;
+            }
+            println!("renamed project '{}' to '{}'", old, new);
+        }
+        Ok(false) => {
+            eprintln!(
+                "error: could not rename project '{}': it is the default project or '{}' already exists",
+                old, new
+            );
+            std::process::exit(1);
+        }
+        Err(e @ TmError::ProjectNotFound(_)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+pub fn handle_list_projects(store: &TaskStore, sort: Option<ProjectSortKey>) {
+    store.list_projects(sort);
 }
 
 pub fn handle_delete_project(
     store: &mut TaskStore,
+    mut config: Config,
     name: String,
+    switch_to: Option<String>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if store.delete_project(name.clone())? {
-        println!("deleted project '{}'", name);
-    } else {
-        eprintln!("error: project '{}' not found or cannot be deleted", name);
+    if switch_to.as_ref() == Some(&name) {
+        eprintln!("error: cannot switch to the project being deleted");
         std::process::exit(1);
     }
+    match store.delete_project(name.clone(), switch_to) {
+        Ok(true) => {
+            if config.focus.as_ref() == Some(&name) {
+                config.focus = None;
+                config.save()?;
+                println!("no longer focused on project '{}'", name);
+            }
+            println!(
+                "deleted project '{}', current project is '{}'",
+                name,
+                store.get_current_project_name()
+            );
+        }
+        Ok(false) if name == "default" => {
+            eprintln!("error: the default project cannot be deleted");
+            std::process::exit(1);
+        }
+        Ok(false) => {
+            eprintln!("error: '{}' is the last remaining project", name);
+            std::process::exit(1);
+        }
+        Err(e @ TmError::ProjectNotFound(_)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e.into()),
+    }
     Ok(())
 }
 
 pub fn handle_update() -> Result<(), Box<dyn std::error::Error>> {
-    println!("🔄 Checking for updates...");
+    println!("{} Checking for updates...", markers().progress);
     println!("Current version: {}", VERSION.green());
 
     // Check latest version from our API
     let latest_version = match get_latest_version() {
         Ok(version) => version,
         Err(e) => {
-            println!("⚠️  Could not check latest version: {}", e);
+            println!(
+                "{} Could not check latest version: {}",
+                markers().warning,
+                e
+            );
             println!("Proceeding with update anyway...");
             "unknown".to_string()
         }
@@ -185,68 +1433,184 @@ pub fn handle_update() -> Result<(), Box<dyn std::error::Error>> {
 
     if latest_version != "unknown" {
         println!("Latest version: {}", latest_version.green());
-        
+
         // Compare versions (remove 'v' prefix if present)
         let current_clean = VERSION.trim_start_matches('v');
         let latest_clean = latest_version.trim_start_matches('v');
-        
-        if current_clean == latest_clean {
-            println!("✅ You're already running the latest version!");
+
+        let up_to_date = match (Version::parse(current_clean), Version::parse(latest_clean)) {
+            (Ok(current), Ok(latest)) => current >= latest,
+            // Fall back to a plain comparison for malformed version strings
+            _ => current_clean == latest_clean,
+        };
+
+        if up_to_date {
+            println!(
+                "{} You're already running the latest version!",
+                markers().success
+            );
             return Ok(());
         }
     }
 
-    println!("");
-    println!("Downloading and running the latest installer...");
+    let asset = match release_asset_name() {
+        Some(asset) => asset,
+        None => update_failed("no prebuilt binary is published for this platform"),
+    };
+
+    let release_path = if latest_version == "unknown" {
+        "latest/download".to_string()
+    } else {
+        format!("download/v{}", latest_version.trim_start_matches('v'))
+    };
+    let binary_url = format!("{}/{}/{}", RELEASES_URL, release_path, asset);
+
+    println!();
+    println!("Downloading {}...", binary_url);
+
+    let binary = download(&binary_url)
+        .unwrap_or_else(|e| update_failed(&format!("could not download binary: {}", e)));
+    let checksum = download(&format!("{}.sha256", binary_url))
+        .unwrap_or_else(|e| update_failed(&format!("could not download checksum: {}", e)));
+    let checksum = String::from_utf8_lossy(&checksum);
+
+    // The checksum file is "<hex digest>  <file name>"
+    let expected = checksum
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    let actual = format!("{:x}", Sha256::digest(&binary));
+
+    if expected != actual {
+        update_failed(&format!(
+            "checksum verification failed (expected {}, got {}), the download was discarded",
+            expected, actual
+        ));
+    }
+
+    println!("{} Verified SHA-256 checksum", markers().check);
+
+    let current_exe = std::env::current_exe()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
 
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(&format!("curl -fsSL {} | bash", INSTALL_SCRIPT_URL))
-        .output()?;
+        // Write next to the current binary so the rename stays on one filesystem
+        let new_exe = current_exe.with_extension("new");
+        fs::write(&new_exe, &binary)?;
+        fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))?;
+        fs::rename(&new_exe, &current_exe)?;
 
-    if output.status.success() {
-        println!("✅ Update completed successfully!");
+        println!("{} Update completed successfully!", markers().success);
         println!("Run 'tm --version' to verify the new version.");
-    } else {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        eprintln!("❌ Update failed: {}", error_msg);
-        eprintln!("");
-        eprintln!("You can try updating manually:");
-        eprintln!("  curl -fsSL {} | bash", INSTALL_SCRIPT_URL);
-        std::process::exit(1);
+    }
+
+    #[cfg(windows)]
+    {
+        // A running executable can't be replaced on Windows
+        let new_exe = std::env::temp_dir().join(asset);
+        fs::write(&new_exe, &binary)?;
+
+        println!(
+            "{} Windows detected - the running binary can't replace itself",
+            markers().warning
+        );
+        println!("   Downloaded the new version to: {}", new_exe.display());
+        println!(
+            "   To finish, run: move /Y \"{}\" \"{}\"",
+            new_exe.display(),
+            current_exe.display()
+        );
     }
 
     Ok(())
 }
 
-fn get_latest_version() -> Result<String, Box<dyn std::error::Error>> {
-    let output = Command::new("curl")
-        .arg("-sL")
-        .arg("https://tm-cli.com/api/version")
-        .output()?;
+fn update_failed(message: &str) -> ! {
+    eprintln!("{} Update failed: {}", markers().failure, message);
+    eprintln!();
+    eprintln!("You can try updating manually:");
+    eprintln!("  curl -fsSL {} | bash", INSTALL_SCRIPT_URL);
+    std::process::exit(1);
+}
 
-    if !output.status.success() {
-        return Err("Failed to fetch version from API".into());
+fn release_asset_name() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("tm-linux-x86_64"),
+        ("linux", "aarch64") => Some("tm-linux-aarch64"),
+        ("macos", "x86_64") => Some("tm-macos-x86_64"),
+        ("macos", "aarch64") => Some("tm-macos-aarch64"),
+        ("windows", "x86_64") => Some("tm-windows-x86_64.exe"),
+        _ => None,
     }
+}
+
+fn download(url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    ureq::get(url)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn get_latest_version() -> Result<String, Box<dyn std::error::Error>> {
+    let body = ureq::get(VERSION_API_URL).call()?.into_string()?;
+    let response: serde_json::Value = serde_json::from_str(&body)?;
 
-    let response = String::from_utf8(output.stdout)?;
-    
-    // Simple JSON parsing to extract version
-    if let Some(start) = response.find("\"version\":\"") {
-        let start = start + 11; // Length of "\"version\":\""
-        if let Some(end) = response[start..].find("\"") {
-            let version = &response[start..start + end];
-            return Ok(version.to_string());
+    response["version"]
+        .as_str()
+        .map(|version| version.to_string())
+        .ok_or_else(|| "Could not parse version from API response".into())
+}
+
+pub fn handle_config(
+    mut config: Config,
+    action: ConfigAction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ConfigAction::Get { key } => println!("{}", config.get(&key)?),
+        ConfigAction::Set { key, value } => {
+            config.set(&key, &value)?;
+            config.save()?;
+            if value.is_empty() {
+                println!("unset {}", key);
+            } else {
+                println!("set {} to {}", key, value);
+            }
         }
     }
-    
-    Err("Could not parse version from API response".into())
+    Ok(())
 }
 
 pub fn handle_version() {
     println!("tm {}", VERSION);
 }
 
+pub fn handle_info(store: &TaskStore) {
+    let path = store.file_path();
+    println!("tm {}", VERSION);
+    println!();
+    println!("data file:  {}", path.display());
+    match fs::metadata(path) {
+        Ok(metadata) => println!("size:       {} bytes", metadata.len()),
+        Err(_) => println!("size:       {}", "file does not exist yet".yellow()),
+    }
+
+    let all_stats = store.all_project_stats();
+    let total: usize = all_stats.iter().map(|(_, stats)| stats.total).sum();
+    println!("projects:   {}", all_stats.len());
+    println!("tasks:      {}", total);
+    println!("current:    {}", store.get_current_project_name().green());
+}
+
+pub fn handle_completions(shell: Shell) {
+    let mut command = Cli::command();
+    clap_complete::generate(shell, &mut command, "tm", &mut std::io::stdout());
+}
+
 pub fn handle_uninstall(yes: bool) -> Result<(), Box<dyn std::error::Error>> {
     let data_dir = get_data_directory()?;
 
@@ -254,10 +1618,19 @@ pub fn handle_uninstall(yes: bool) -> Result<(), Box<dyn std::error::Error>> {
     let current_exe = std::env::current_exe()?;
 
     if !yes {
-        println!("⚠️  This will permanently delete:");
-        println!("   • ALL your task data: {}", data_dir.display());
-        println!("   • TM CLI binary: {}", current_exe.display());
-        println!("");
+        let markers = markers();
+        println!("{} This will permanently delete:", markers.warning);
+        println!(
+            "   {} ALL your task data: {}",
+            markers.bullet,
+            data_dir.display()
+        );
+        println!(
+            "   {} TM CLI binary: {}",
+            markers.bullet,
+            current_exe.display()
+        );
+        println!();
         print!("Are you sure you want to continue? (y/N): ");
         use std::io::{self, Write};
         io::stdout().flush()?;
@@ -274,37 +1647,78 @@ pub fn handle_uninstall(yes: bool) -> Result<(), Box<dyn std::error::Error>> {
     // Remove data directory
     if data_dir.exists() {
         fs::remove_dir_all(&data_dir)?;
-        println!("✓ Removed all task data from {}", data_dir.display());
+        println!(
+            "{} Removed all task data from {}",
+            markers().check,
+            data_dir.display()
+        );
     } else {
         println!("No data found to remove");
     }
 
     // Remove the binary itself
-    println!("✓ Removing TM CLI binary from {}", current_exe.display());
+    println!(
+        "{} Removing TM CLI binary from {}",
+        markers().check,
+        current_exe.display()
+    );
 
     // We need to delete ourselves, which requires special handling
     #[cfg(unix)]
     {
         // On Unix systems, we can delete the file while it's running
         if let Err(e) = fs::remove_file(&current_exe) {
-            println!("⚠️  Could not remove binary automatically: {}", e);
+            println!(
+                "{} Could not remove binary automatically: {}",
+                markers().warning,
+                e
+            );
             println!("   Please manually remove: {}", current_exe.display());
         } else {
-            println!("✓ Removed TM CLI binary");
+            println!("{} Removed TM CLI binary", markers().check);
         }
     }
 
     #[cfg(windows)]
     {
         // On Windows, we need to use a different approach
-        println!("⚠️  Windows detected - binary removal requires manual deletion");
+        println!(
+            "{} Windows detected - binary removal requires manual deletion",
+            markers().warning
+        );
         println!("   Please manually remove: {}", current_exe.display());
         println!("   Or run: del \"{}\"", current_exe.display());
     }
 
-    println!("");
-    println!("✅ TM CLI has been uninstalled successfully!");
+    println!();
+    println!(
+        "{} TM CLI has been uninstalled successfully!",
+        markers().success
+    );
     println!("   Thank you for using TM CLI!");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_needs_exactly_one_flag() {
+        // Every combination of the five flags, position set to 3 when on
+        for mask in 0u8..32 {
+            let flag = |bit: u8| mask & (1 << bit) != 0;
+            let position = flag(4).then_some(3);
+            let result = direction(flag(0), flag(1), flag(2), flag(3), position);
+            match mask.count_ones() {
+                1 => {
+                    let expected =
+                        ["up", "down", "top", "bottom", "3"][mask.trailing_zeros() as usize];
+                    assert_eq!(result.as_deref(), Ok(expected), "flags {:05b}", mask);
+                }
+                _ => assert!(result.is_err(), "flags {:05b}", mask),
+            }
+        }
+    }
+}
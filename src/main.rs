@@ -1,16 +1,30 @@
 use clap::Parser;
+use std::io::IsTerminal;
 
 mod commands;
+mod config;
+mod error;
 mod handlers;
 mod models;
 mod store;
 mod utils;
 
-use commands::Commands;
+use commands::{Cli, Commands};
+use config::Config;
+use error::TmError;
 use handlers::*;
-use store::TaskStore;
+use models::Task;
+use store::{ListOptions, TaskStore};
+use utils::{parse_path, parse_range};
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
     // Check for version flags first
     let args: Vec<String> = std::env::args().collect();
     if args.len() == 2 && (args[1] == "-v" || args[1] == "--version") {
@@ -18,33 +32,257 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let commands = Commands::parse();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e @ TmError::InvalidConfig(_)) => {
+            eprintln!("warning: {}; ignoring the settings that can't be read", e);
+            Config::load_valid()
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    // Disable colors when output is piped, NO_COLOR is set or the config
+    // turns them off
+    if !std::io::stdout().is_terminal() || std::env::var_os("NO_COLOR").is_some() || !config.color {
+        colored::control::set_override(false);
+    }
 
-    let mut store = TaskStore::new()?;
-    store.load()?;
+    let cli = Cli::parse();
+    utils::set_ascii(cli.ascii || utils::detect_ascii());
 
-    match commands {
-        Commands::Add { path, text } => {
-            handle_add(&mut store, path, text)?;
+    let mut store = match cli.file {
+        Some(path) => TaskStore::with_path(path),
+        None => TaskStore::new()?,
+    };
+    store.with_config(&config);
+    // Checked before loading, which would repair (or set aside) the file
+    if let Commands::CheckData = cli.command {
+        return handle_check_data(&store);
+    }
+    match store.load() {
+        Err(e @ TmError::CorruptData { .. }) => handle_corrupt_data(&mut store, e)?,
+        result => result?,
+    }
+    if let Some(name) = &cli.project_context {
+        store.with_project_context(name)?;
+    } else if let Some(name) = &config.focus {
+        // Falling back to the current project would quietly act on the
+        // wrong one, so only the commands that fix the focus get past this
+        let fixes_focus = matches!(
+            cli.command,
+            Commands::Focus { .. } | Commands::Unfocus | Commands::Config { .. }
+        );
+        if store.with_project_context(name).is_err() && !fixes_focus {
+            eprintln!(
+                "error: focused project '{}' no longer exists, run `tm unfocus`",
+                name
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // Archived after the command runs, so the indices it was given still
+    // match what the user last saw; only done when the command changed
+    // something, so read-only commands never write, and skipped on undo and
+    // redo to keep history intact
+    let auto_archive_days = config
+        .auto_archive_days
+        .filter(|_| !matches!(cli.command, Commands::Undo | Commands::Redo));
+
+    match cli.command {
+        Commands::Add {
+            path,
+            under,
+            text,
+            due,
+            priority,
+            tags,
+            repeat,
+            editor,
+            top,
+            after,
+            before,
+            stdin,
+        } => {
+            let (text, path) = if editor {
+                // Without task text, every positional is part of the parent path
+                let mut parent = match text {
+                    Some(first) => parse_path(&[first])?,
+                    None => Vec::new(),
+                };
+                parent.extend(path);
+                (read_task_from_editor()?, parent)
+            } else {
+                (text.unwrap_or_default(), path)
+            };
+            let task = Task {
+                due,
+                priority,
+                tags,
+                recurrence: repeat,
+                ..Task::new(text)
+            };
+            if stdin {
+                handle_add_stdin(&mut store, task)?;
+            } else {
+                handle_add(&mut store, path, under, task, top, after, before)?;
+            }
+        }
+        Commands::List {
+            project,
+            all,
+            json,
+            plain,
+            porcelain,
+            sort,
+            reverse,
+            tag,
+            open,
+            done,
+            depth,
+            no_wrap,
+            times,
+        } => {
+            let options = ListOptions {
+                sort: sort.or(config.default_sort),
+                reverse,
+                tag,
+                completed: match (open, done) {
+                    (true, _) => Some(false),
+                    (_, true) => Some(true),
+                    _ => None,
+                },
+                max_depth: depth,
+                max_width: if no_wrap {
+                    None
+                } else {
+                    utils::terminal_width()
+                },
+                times,
+            };
+            handle_list(&mut store, project, all, json, plain, porcelain, options)?;
+        }
+        Commands::Rename { path, text } => {
+            handle_rename(&mut store, path, text)?;
+        }
+        Commands::Split { path } => {
+            handle_split(&mut store, path)?;
+        }
+        Commands::SetPriority { path, level } => {
+            handle_set_priority(&mut store, path, level)?;
+        }
+        Commands::Note { path, text, clear } => match clear {
+            Some(path) => handle_note(&mut store, path, None)?,
+            None => handle_note(&mut store, path, text)?,
+        },
+        Commands::Tag { path, tags } => {
+            handle_tag(&mut store, path, tags)?;
+        }
+        Commands::Show { path } => {
+            handle_show(&mut store, path);
+        }
+        Commands::Open { path } => {
+            handle_open(&mut store, path)?;
+        }
+        Commands::Search {
+            query,
+            all_projects,
+            count,
+        } => {
+            handle_search(&mut store, query, all_projects, count);
+        }
+        Commands::Next { deep } => {
+            handle_next(&mut store, deep);
+        }
+        Commands::Log {
+            limit,
+            all_projects,
+        } => {
+            handle_log(&mut store, limit, all_projects);
+        }
+        Commands::Remind { notify, bell } => {
+            handle_remind(&store, notify, bell);
         }
-        Commands::List => {
-            handle_list(&mut store);
+        Commands::Today { overdue } => {
+            handle_today(&mut store, overdue);
+        }
+        Commands::Stats {
+            all_projects,
+            since,
+            format,
+        } => {
+            handle_stats(&mut store, all_projects, since, format)?;
+        }
+        Commands::Count { all, recursive } => {
+            handle_count(&mut store, all, recursive);
+        }
+        Commands::Duplicate { path } => {
+            handle_duplicate(&mut store, path)?;
         }
         Commands::Clear => {
             handle_clear(&mut store)?;
         }
-        Commands::Delete { path } => {
-            handle_delete(&mut store, path)?;
+        Commands::PruneEmpty => {
+            handle_prune_empty(&mut store)?;
+        }
+        Commands::Delete { path, id } => match (id, parse_range(&path)) {
+            (Some(id), _) => {
+                let path = resolve_id_path(&mut store, &id);
+                handle_delete(&mut store, path)?
+            }
+            (None, Some(indices)) => handle_delete_many(&mut store, indices?)?,
+            (None, None) => handle_delete(&mut store, parse_path(&path)?)?,
+        },
+        Commands::Pending { verbose } => {
+            handle_pending(&mut store, verbose);
+        }
+        Commands::Check {
+            path,
+            text,
+            id,
+            comment,
+            no_history,
+        } => {
+            let comment = comment.as_deref().map(str::trim).filter(|c| !c.is_empty());
+            match (id, parse_range(&path)) {
+                (Some(id), _) => {
+                    let path = resolve_id_path(&mut store, &id);
+                    handle_check(&mut store, path, None, !no_history, comment)?
+                }
+                (None, Some(indices)) => {
+                    handle_check_many(&mut store, indices?, !no_history, comment)?
+                }
+                (None, None) => {
+                    let path = parse_path(&path)?;
+                    handle_check(&mut store, path, text, !no_history, comment)?
+                }
+            }
+        }
+        Commands::CheckAll { yes } => {
+            handle_check_all(&mut store, yes)?;
         }
-        Commands::Check { path } => {
-            handle_check(&mut store, path)?;
+        Commands::CheckMany {
+            indices,
+            no_history,
+        } => {
+            handle_check_many(&mut store, indices, !no_history, None)?;
         }
         Commands::Uncheck { path } => {
             handle_uncheck(&mut store, path)?;
         }
+        Commands::Archive { list, restore } => {
+            handle_archive(&mut store, list, restore)?;
+        }
         Commands::ClearAll => {
             handle_clear_all(&mut store)?;
         }
+        Commands::Undo => {
+            handle_undo(&mut store)?;
+        }
+        Commands::Redo => {
+            handle_redo(&mut store)?;
+        }
+        Commands::CheckData => unreachable!("handled before loading"),
         Commands::Move {
             path,
             up,
@@ -52,31 +290,89 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             top,
             bottom,
             position,
-        } => {
-            handle_move(&mut store, path, up, down, top, bottom, position)?;
+            before,
+            after,
+            into_before,
+        } => match (before, after, into_before) {
+            (Some(target), _, _) => handle_move_relative(&mut store, path, target, false, false)?,
+            (_, Some(target), _) => handle_move_relative(&mut store, path, target, true, false)?,
+            (_, _, Some(target)) => handle_move_relative(&mut store, path, target, false, true)?,
+            _ => handle_move(&mut store, path, up, down, top, bottom, position)?,
+        },
+        Commands::Reparent { path, to } => {
+            handle_reparent(&mut store, path, to.unwrap_or_default())?;
         }
-        Commands::CreateProject { name } => {
-            handle_create_project(&mut store, name)?;
+        Commands::Promote { path } => {
+            handle_promote(&mut store, path)?;
         }
-        Commands::SwitchProject { name } => {
-            handle_switch_project(&mut store, name)?;
+        Commands::MoveTask { path, to } => {
+            handle_move_task(&mut store, path, to)?;
         }
-        Commands::ListProjects => {
-            handle_list_projects(&store);
+        Commands::Export { format, output } => {
+            handle_export(&mut store, format, output, cli.project_context)?;
         }
-        Commands::DeleteProject { name } => {
-            handle_delete_project(&mut store, name)?;
+        Commands::Import { input, merge } => {
+            handle_import(&mut store, input, merge)?;
+        }
+        Commands::ImportText { input } => {
+            handle_import_text(&mut store, input)?;
+        }
+        Commands::CreateProject { name, color } => {
+            handle_create_project(&mut store, name, color)?;
+        }
+        Commands::SetProjectColor { name, color } => {
+            handle_set_project_color(&mut store, name, color)?;
+        }
+        Commands::SwitchProject { name, force } => {
+            handle_switch_project(&mut store, config, name, force)?;
+        }
+        Commands::Focus { name } => {
+            handle_focus(&store, config, name)?;
+        }
+        Commands::Unfocus => {
+            handle_unfocus(config)?;
+        }
+        Commands::RenameProject { old, new } => {
+            handle_rename_project(&mut store, config, old, new)?;
+        }
+        Commands::ListProjects { sort } => {
+            handle_list_projects(&store, sort);
+        }
+        Commands::DeleteProject { name, switch_to } => {
+            handle_delete_project(&mut store, config, name, switch_to)?;
         }
         Commands::Update => {
             handle_update()?;
         }
         Commands::Uninstall { yes } => {
+            // Release the lock file so the data directory can be removed
+            drop(store);
             handle_uninstall(yes)?;
+            return Ok(());
+        }
+        Commands::Config { action } => {
+            handle_config(config, action)?;
         }
         Commands::Version => {
             handle_version();
         }
+        Commands::Info => {
+            handle_info(&store);
+        }
+        Commands::Completions { shell } => {
+            handle_completions(shell);
+        }
     }
 
+    if let Some(days) = auto_archive_days.filter(|_| store.is_dirty()) {
+        let archived = store.auto_archive(days);
+        if archived > 0 {
+            eprintln!(
+                "archived {} task(s) completed more than {} days ago",
+                archived, days
+            );
+        }
+    }
+    store.flush()?;
     Ok(())
 }
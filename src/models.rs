@@ -1,20 +1,106 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Months, Utc};
+use clap::ValueEnum;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Task {
+    /// stable identifier that survives reordering; backfilled on load for
+    /// tasks saved before ids existed
+    #[serde(default)]
+    pub id: String,
     pub text: String,
     pub completed: bool,
     pub created_at: DateTime<Utc>,
     pub completed_at: Option<DateTime<Utc>>,
+    /// how the task was resolved, given with `check --comment`
+    #[serde(default)]
+    pub completion_note: Option<String>,
+    #[serde(default)]
+    pub due: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub note: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
     pub subtasks: Vec<Task>,
 }
 
+impl Task {
+    pub fn new(text: String) -> Self {
+        Self {
+            id: new_task_id(),
+            text,
+            completed: false,
+            created_at: Utc::now(),
+            completed_at: None,
+            completion_note: None,
+            due: None,
+            priority: None,
+            note: None,
+            tags: Vec::new(),
+            recurrence: None,
+            subtasks: Vec::new(),
+        }
+    }
+}
+
+pub fn new_task_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Recurrence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recurrence::Daily => "daily",
+            Recurrence::Weekly => "weekly",
+            Recurrence::Monthly => "monthly",
+        }
+    }
+
+    pub fn advance(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Recurrence::Daily => date + chrono::Duration::days(1),
+            Recurrence::Weekly => date + chrono::Duration::weeks(1),
+            Recurrence::Monthly => date.checked_add_months(Months::new(1)).unwrap_or(date),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Project {
     pub name: String,
     pub tasks: Vec<Task>,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub archived: Vec<Task>,
+    /// color name the project is shown in, e.g. "blue"
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl Project {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            tasks: Vec::new(),
+            created_at: Utc::now(),
+            archived: Vec::new(),
+            color: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -22,3 +108,50 @@ pub struct ProjectStore {
     pub current_project: String,
     pub projects: Vec<Project>,
 }
+
+pub struct ProjectStats {
+    pub total: usize,
+    pub completed: usize,
+    pub oldest_open: Option<DateTime<Utc>>,
+}
+
+// Serialized with the derived figures rather than the raw fields, so JSON
+// consumers don't have to recompute them
+impl Serialize for ProjectStats {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ProjectStats", 5)?;
+        state.serialize_field("total", &self.total)?;
+        state.serialize_field("completed", &self.completed)?;
+        state.serialize_field("open", &self.open())?;
+        state.serialize_field("completion_pct", &self.completion_pct())?;
+        state.serialize_field(
+            "oldest_open_age_secs",
+            &self
+                .oldest_open
+                .map(|oldest| (Utc::now() - oldest).num_seconds()),
+        )?;
+        state.end()
+    }
+}
+
+impl ProjectStats {
+    pub fn open(&self) -> usize {
+        self.total - self.completed
+    }
+
+    pub fn completion_pct(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.completed as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+pub struct CompletedEntry {
+    pub project: String,
+    pub path: Vec<usize>,
+    pub text: String,
+    pub completed_at: DateTime<Utc>,
+    pub note: Option<String>,
+}
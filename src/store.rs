@@ -1,68 +1,503 @@
-use chrono::Utc;
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use colored::Colorize;
-use serde_json;
-use std::fs;
-use std::path::PathBuf;
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::models::{Project, ProjectStore, Task};
-use crate::utils::get_data_file_path;
+use crate::commands::{ProjectSortKey, SortKey, TimesFormat};
+use crate::config::Config;
+use crate::error::TmError;
+use crate::models::{new_task_id, CompletedEntry, Project, ProjectStats, ProjectStore, Task};
+use crate::utils::{
+    display_width, format_path, get_data_file_path, get_legacy_data_file_path, humanize, markers,
+    pad, truncate, validate_project_name,
+};
+
+const NOTE_PREVIEW_LEN: usize = 60;
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+// serde_json refuses to load JSON nested deeper than 128 levels, and every
+// level of tasks takes two of them; staying well below that also keeps the
+// recursive tree walks far from overflowing the stack
+const MAX_TASK_DEPTH: usize = 50;
+// Undo keeps at most this many earlier states, and drops the oldest ones
+// sooner if together they take more than MAX_HISTORY_BYTES
+const MAX_HISTORY: usize = 20;
+const MAX_HISTORY_BYTES: u64 = 20 * 1024 * 1024;
+const UNDO_STACK: &str = "undo";
+const REDO_STACK: &str = "redo";
+
+type PathMatches<'a> = Vec<(Vec<usize>, &'a Task)>;
+
+#[derive(Default)]
+pub struct ListOptions {
+    pub sort: Option<SortKey>,
+    pub reverse: bool,
+    pub tag: Option<String>,
+    /// only show open (false) or completed (true) tasks
+    pub completed: Option<bool>,
+    pub max_depth: Option<usize>,
+    /// truncate task lines to this many columns
+    pub max_width: Option<usize>,
+    pub times: Option<TimesFormat>,
+}
 
 pub struct TaskStore {
     file_path: PathBuf,
     store: ProjectStore,
+    // Held from load until the store is dropped; closing the file releases it
+    lock: Option<File>,
+    project_context: Option<String>,
+    // Set by every mutation; `flush` writes the file once at the end of a run
+    dirty: bool,
+    compact: bool,
+    default_project: String,
 }
 
 impl TaskStore {
-    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new() -> Result<Self, TmError> {
         let file_path = get_data_file_path()?;
-        Ok(Self {
+        Self::migrate_legacy_data(&file_path)?;
+        Ok(Self::with_path(file_path))
+    }
+
+    fn migrate_legacy_data(file_path: &Path) -> Result<(), TmError> {
+        if file_path.exists() {
+            return Ok(());
+        }
+
+        if let Some(legacy_path) = get_legacy_data_file_path().filter(|p| p.exists()) {
+            // The old todos are field-compatible with Task, so copying the file
+            // lets load() handle the array-of-tasks format as usual
+            fs::copy(&legacy_path, file_path)?;
+            eprintln!(
+                "migrated tasks from {} to {}",
+                legacy_path.display(),
+                file_path.display()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn with_path(file_path: PathBuf) -> Self {
+        Self {
             file_path,
             store: ProjectStore {
                 current_project: "default".to_string(),
-                projects: vec![Project {
-                    name: "default".to_string(),
-                    tasks: Vec::new(),
-                    created_at: Utc::now(),
-                }],
+                projects: vec![Project::new("default".to_string())],
             },
-        })
+            lock: None,
+            project_context: None,
+            dirty: false,
+            compact: false,
+            default_project: "default".to_string(),
+        }
     }
 
-    pub fn load(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Applies the defaults from config.toml; call before `load`.
+    pub fn with_config(&mut self, config: &Config) {
+        self.compact = config.compact;
+        if let Some(name) = &config.default_project {
+            self.default_project = name.clone();
+        }
+    }
+
+    fn acquire_lock(&self) -> Result<File, TmError> {
+        let lock_file = File::create(self.sibling_path(".lock"))?;
+        let started = Instant::now();
+        while lock_file.try_lock_exclusive().is_err() {
+            if started.elapsed() >= LOCK_TIMEOUT {
+                return Err(TmError::Locked);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Ok(lock_file)
+    }
+
+    pub fn load(&mut self) -> Result<(), TmError> {
+        if self.lock.is_none() {
+            self.lock = Some(self.acquire_lock()?);
+        }
+
         if self.file_path.exists() {
             let content = fs::read_to_string(&self.file_path)?;
 
             // Try to deserialize as new format first
-            if let Ok(store) = serde_json::from_str::<ProjectStore>(&content) {
+            let parsed = Self::parse::<ProjectStore>(&content);
+            if let Ok(store) = parsed {
                 self.store = store;
             } else {
                 // Try to deserialize as old format (array of tasks) and migrate
-                if let Ok(tasks) = serde_json::from_str::<Vec<Task>>(&content) {
+                let legacy = Self::parse::<Vec<Task>>(&content);
+                if let Ok(tasks) = legacy {
                     self.store = ProjectStore {
                         current_project: "default".to_string(),
                         projects: vec![Project {
-                            name: "default".to_string(),
                             tasks,
-                            created_at: Utc::now(),
+                            ..Project::new("default".to_string())
                         }],
                     };
-                    // Save the migrated data
-                    self.save()?;
+                    // Not marked dirty: the new format is written with the
+                    // next change, so read-only commands leave the file alone
                 } else {
-                    return Err("Invalid data format in tasks.json".into());
+                    // Report against whichever format the file looks like
+                    let reason = if content.trim_start().starts_with('[') {
+                        legacy.err()
+                    } else {
+                        parsed.err()
+                    };
+                    return Err(self.set_aside_corrupt_file(reason.unwrap_or_default())?);
                 }
             }
+        } else {
+            // "default" always exists; the configured project only decides
+            // where a new file starts out
+            let mut projects = vec![Project::new("default".to_string())];
+            if self.default_project != "default" {
+                projects.push(Project::new(self.default_project.clone()));
+            }
+            self.store = ProjectStore {
+                current_project: self.default_project.clone(),
+                projects,
+            };
+        }
+        self.ensure_current_project();
+
+        // Repairs only fix the in-memory copy; like the migration above, they
+        // reach the file along with the next real change
+        let mut assigned = false;
+        for project in &mut self.store.projects {
+            Self::repair_timestamps(&mut project.tasks);
+            Self::repair_timestamps(&mut project.archived);
+            assigned |= Self::assign_missing_ids(&mut project.tasks);
+            assigned |= Self::assign_missing_ids(&mut project.archived);
+        }
+        // New ids are the exception: they must be saved right away, or they
+        // would change on every run until something else is written
+        if assigned {
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Gives every task without an id a new one. Returns whether any did.
+    fn assign_missing_ids(tasks: &mut [Task]) -> bool {
+        let mut assigned = false;
+        for task in tasks {
+            if task.id.is_empty() {
+                task.id = new_task_id();
+                assigned = true;
+            }
+            assigned |= Self::assign_missing_ids(&mut task.subtasks);
+        }
+        assigned
+    }
+
+    fn collect_ids(tasks: &[Task], ids: &mut HashSet<String>) {
+        for task in tasks {
+            ids.insert(task.id.clone());
+            Self::collect_ids(&task.subtasks, ids);
+        }
+    }
+
+    /// Gives a new id to every task whose id is missing or already in
+    /// `seen`, recording the ones it keeps.
+    fn assign_unique_ids(tasks: &mut [Task], seen: &mut HashSet<String>) {
+        for task in tasks {
+            if task.id.is_empty() || seen.contains(&task.id) {
+                task.id = new_task_id();
+            }
+            seen.insert(task.id.clone());
+            Self::assign_unique_ids(&mut task.subtasks, seen);
+        }
+    }
+
+    /// Makes `completed_at` agree with `completed` (hand-edited files or old
+    /// versions can get them out of sync).
+    fn repair_timestamps(tasks: &mut [Task]) {
+        for task in tasks {
+            if task.completed && task.completed_at.is_none() {
+                task.completed_at = Some(task.created_at);
+            } else if !task.completed {
+                task.completed_at = None;
+            }
+            Self::repair_timestamps(&mut task.subtasks);
+        }
+    }
+
+    /// Reads the data file as it is on disk, without the repairs `load`
+    /// makes, and describes everything wrong with it. Never writes.
+    pub fn check_data(&self) -> Result<Vec<String>, TmError> {
+        if !self.file_path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.file_path)?;
+        let store = match Self::parse::<ProjectStore>(&content) {
+            Ok(store) => store,
+            Err(reason) => match Self::parse::<Vec<Task>>(&content) {
+                Ok(tasks) => ProjectStore {
+                    current_project: "default".to_string(),
+                    projects: vec![Project {
+                        tasks,
+                        ..Project::new("default".to_string())
+                    }],
+                },
+                Err(_) => return Ok(vec![format!("file can't be parsed: {}", reason)]),
+            },
+        };
+
+        let mut issues = Vec::new();
+        let mut names = HashSet::new();
+        for project in &store.projects {
+            if !names.insert(project.name.as_str()) {
+                issues.push(format!("project '{}' appears more than once", project.name));
+            }
+            if let Err(reason) = validate_project_name(&project.name) {
+                issues.push(format!("project '{}': {}", project.name, reason));
+            }
+        }
+        if !names.contains("default") {
+            issues.push("there is no 'default' project".to_string());
+        }
+        if !names.contains(store.current_project.as_str()) {
+            issues.push(format!(
+                "current project '{}' doesn't exist",
+                store.current_project
+            ));
+        }
+
+        let mut ids = HashSet::new();
+        for project in &store.projects {
+            for (list, label) in [
+                (&project.tasks, "task"),
+                (&project.archived, "archived task"),
+            ] {
+                let mut prefix = Vec::new();
+                Self::check_tasks(list, &mut prefix, &mut ids, &mut |path, problem| {
+                    issues.push(format!(
+                        "project '{}': {} {}: {}",
+                        project.name,
+                        label,
+                        format_path(path),
+                        problem
+                    ))
+                });
+            }
+        }
+        Ok(issues)
+    }
+
+    fn check_tasks<'a>(
+        tasks: &'a [Task],
+        path: &mut Vec<usize>,
+        ids: &mut HashSet<&'a str>,
+        report: &mut impl FnMut(&[usize], &str),
+    ) {
+        for (i, task) in tasks.iter().enumerate() {
+            path.push(i);
+            if task.text.trim().is_empty() {
+                report(path, "empty text");
+            }
+            if task.completed && task.completed_at.is_none() {
+                report(path, "completed but has no completion time");
+            } else if !task.completed && task.completed_at.is_some() {
+                report(path, "open but has a completion time");
+            }
+            if !task.id.is_empty() && !ids.insert(&task.id) {
+                report(path, &format!("id {} is used by another task", task.id));
+            }
+            if path.len() > MAX_TASK_DEPTH {
+                report(
+                    path,
+                    &format!("nested deeper than {} levels", MAX_TASK_DEPTH),
+                );
+            } else {
+                Self::check_tasks(&task.subtasks, path, ids, report);
+            }
+            path.pop();
+        }
+    }
+
+    /// Deserializes `content`, describing failures with the path of the
+    /// offending value, e.g. "projects[0].tasks[2]: missing field `text` at
+    /// line 12 column 5".
+    fn parse<T: DeserializeOwned>(content: &str) -> Result<T, String> {
+        let mut deserializer = serde_json::Deserializer::from_str(content);
+        serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+            let path = e.path().to_string();
+            if path == "." {
+                e.inner().to_string()
+            } else {
+                format!("{}: {}", path, e.inner())
+            }
+        })
+    }
+
+    /// Moves an unreadable data file out of the way so the next save can't
+    /// overwrite it.
+    fn set_aside_corrupt_file(&self, reason: String) -> Result<TmError, TmError> {
+        let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+        let mut moved_to = self.sibling_path(&format!(".corrupt-{}", timestamp));
+        let mut attempt = 1;
+        while moved_to.exists() {
+            attempt += 1;
+            moved_to = self.sibling_path(&format!(".corrupt-{}-{}", timestamp, attempt));
+        }
+        fs::rename(&self.file_path, &moved_to)?;
+
+        let backup_valid = fs::read_to_string(self.sibling_path(".bak"))
+            .is_ok_and(|content| serde_json::from_str::<ProjectStore>(&content).is_ok());
+        Ok(TmError::CorruptData {
+            reason,
+            moved_to,
+            backup_valid,
+        })
+    }
+
+    pub fn restore_backup(&mut self) -> Result<(), TmError> {
+        fs::copy(self.sibling_path(".bak"), &self.file_path)?;
+        self.load()
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        self.sibling_path(".bak")
+    }
+
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let mut path = self.file_path.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    /// Whether there are changes `flush` would write.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes pending changes, if any, to disk.
+    pub fn flush(&mut self) -> Result<(), TmError> {
+        if self.dirty {
+            self.save()?;
+            self.dirty = false;
         }
         Ok(())
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let content = serde_json::to_string_pretty(&self.store)?;
-        fs::write(&self.file_path, content)?;
+    fn save(&self) -> Result<(), TmError> {
+        // Compact output is smaller and faster to write for large stores, but
+        // hard to edit by hand; either form loads the same way
+        let compact = match std::env::var("TM_COMPACT") {
+            Ok(v) => v == "1" || v == "true",
+            Err(_) => self.compact,
+        };
+        let content = if compact {
+            serde_json::to_string(&self.store)?
+        } else {
+            serde_json::to_string_pretty(&self.store)?
+        };
+
+        // Keep the previous state around so the change can be undone; the
+        // .bak copy is also what recovery falls back to for a corrupt file
+        if self.file_path.exists() {
+            fs::copy(&self.file_path, self.sibling_path(".bak"))?;
+            self.push_snapshot(UNDO_STACK, &self.file_path)?;
+            // A new change forks history, so what was undone can't come back
+            let redo_dir = self.history_dir(REDO_STACK);
+            if redo_dir.exists() {
+                fs::remove_dir_all(redo_dir)?;
+            }
+        }
+
+        // Write to a sibling temp file and rename it over the real one so a
+        // crash mid-write never leaves a truncated tasks.json behind
+        let tmp_path = self.sibling_path(".tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.file_path)?;
         Ok(())
     }
 
-    pub fn get_current_tasks(&mut self) -> &mut Vec<Task> {
+    /// Restores the state before the last change. Returns false when the
+    /// history is empty.
+    pub fn undo(&mut self) -> Result<bool, TmError> {
+        self.step_history(UNDO_STACK, REDO_STACK)
+    }
+
+    /// Reapplies the last undone change. Returns false when nothing has
+    /// been undone since the last change.
+    pub fn redo(&mut self) -> Result<bool, TmError> {
+        self.step_history(REDO_STACK, UNDO_STACK)
+    }
+
+    /// Replaces the tasks file with the newest snapshot on the `from` stack,
+    /// pushing the current file onto the `to` stack so the step can be
+    /// reversed.
+    fn step_history(&mut self, from: &str, to: &str) -> Result<bool, TmError> {
+        let Some(snapshot) = Self::snapshots(&self.history_dir(from))?.pop() else {
+            return Ok(false);
+        };
+        if self.file_path.exists() {
+            self.push_snapshot(to, &self.file_path)?;
+        }
+        fs::rename(snapshot, &self.file_path)?;
+
+        // Anything still pending belonged to the state we just replaced
+        self.dirty = false;
+        self.load()?;
+        Ok(true)
+    }
+
+    fn history_dir(&self, stack: &str) -> PathBuf {
+        self.sibling_path(".history").join(stack)
+    }
+
+    /// Snapshot files in `dir`, oldest first.
+    fn snapshots(dir: &Path) -> Result<Vec<PathBuf>, TmError> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Names are zero-padded sequence numbers, so they sort by age
+        snapshots.sort();
+        Ok(snapshots)
+    }
+
+    /// Copies `source` onto the top of `stack`, then drops the oldest
+    /// snapshots until the stack fits within the history limits.
+    fn push_snapshot(&self, stack: &str, source: &Path) -> Result<(), TmError> {
+        let dir = self.history_dir(stack);
+        fs::create_dir_all(&dir)?;
+        let mut snapshots = Self::snapshots(&dir)?;
+        let next = snapshots
+            .last()
+            .and_then(|path| path.file_stem()?.to_str()?.parse::<u64>().ok())
+            .map_or(0, |last| last + 1);
+        let path = dir.join(format!("{:010}.json", next));
+        fs::copy(source, &path)?;
+        snapshots.push(path);
+
+        let sizes = snapshots
+            .iter()
+            .map(|path| Ok(fs::metadata(path)?.len()))
+            .collect::<Result<Vec<_>, TmError>>()?;
+        // The newest snapshot is always kept, however large
+        let mut oldest = 0;
+        while snapshots.len() - oldest > 1
+            && (snapshots.len() - oldest > MAX_HISTORY
+                || sizes[oldest..].iter().sum::<u64>() > MAX_HISTORY_BYTES)
+        {
+            fs::remove_file(&snapshots[oldest])?;
+            oldest += 1;
+        }
+        Ok(())
+    }
+
+    fn ensure_current_project(&mut self) {
         // Ensure current project exists, create default if needed
         if !self
             .store
@@ -72,171 +507,1247 @@ impl TaskStore {
         {
             self.store.current_project = "default".to_string();
             if !self.store.projects.iter().any(|p| p.name == "default") {
-                self.store.projects.push(Project {
-                    name: "default".to_string(),
-                    tasks: Vec::new(),
-                    created_at: Utc::now(),
+                self.store
+                    .projects
+                    .push(Project::new("default".to_string()));
+            }
+        }
+    }
+
+    /// Scopes every current-project operation to `name` for the lifetime of
+    /// this store, without changing the saved current project.
+    pub fn with_project_context(&mut self, name: &str) -> Result<(), TmError> {
+        if !self.store.projects.iter().any(|p| p.name == name) {
+            return Err(TmError::ProjectNotFound(name.to_string()));
+        }
+        self.project_context = Some(name.to_string());
+        Ok(())
+    }
+
+    fn get_current_project(&mut self) -> &mut Project {
+        self.ensure_current_project();
+
+        // Now safely get the current project
+        let name = self.get_current_project_name().to_string();
+        self.store
+            .projects
+            .iter_mut()
+            .find(|p| p.name == name)
+            .unwrap()
+    }
+
+    pub fn get_current_tasks(&mut self) -> &mut Vec<Task> {
+        &mut self.get_current_project().tasks
+    }
+
+    pub fn list_project_tasks(&self, name: &str) -> Option<&Vec<Task>> {
+        self.store
+            .projects
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| &p.tasks)
+    }
+
+    /// Adds `task` under the parent at `path`, at `position` in its list or
+    /// at the end when that's `None`. Returns false if the parent doesn't
+    /// exist.
+    pub fn add_task(
+        &mut self,
+        path: Vec<usize>,
+        mut task: Task,
+        position: Option<usize>,
+    ) -> Result<bool, TmError> {
+        task.text = Self::trimmed_text(&task.text)?;
+        Self::check_depth(path.len(), &task)?;
+        let Some(tasks) = self.children_list(&path) else {
+            return Ok(false);
+        };
+        match position {
+            Some(position) if position > tasks.len() => {
+                let mut missing = path;
+                missing.push(position);
+                return Err(TmError::PathNotFound(missing));
+            }
+            Some(position) => tasks.insert(position, task),
+            None => tasks.push(task),
+        }
+        self.dirty = true;
+        Ok(true)
+    }
+
+    /// Errors if `task` (with its subtasks) would nest too deeply when placed
+    /// under a parent at `parent_depth` (0 for the root list).
+    fn check_depth(parent_depth: usize, task: &Task) -> Result<(), TmError> {
+        if parent_depth + Self::height(task) > MAX_TASK_DEPTH {
+            return Err(TmError::TooDeep(MAX_TASK_DEPTH));
+        }
+        Ok(())
+    }
+
+    /// Number of levels in `task`'s subtree, counting itself.
+    fn height(task: &Task) -> usize {
+        1 + task.subtasks.iter().map(Self::height).max().unwrap_or(0)
+    }
+
+    /// Marks the task at `path` and its ancestors incomplete again, so a
+    /// new open subtask doesn't sit under a finished parent. Returns the paths
+    /// that were reopened, outermost first.
+    pub fn reopen_ancestors(&mut self, path: &[usize]) -> Vec<Vec<usize>> {
+        let mut reopened = Vec::new();
+        for depth in 1..=path.len() {
+            if let Some(task) = self.find_item(path[..depth].to_vec()) {
+                if task.completed {
+                    task.completed = false;
+                    task.completed_at = None;
+                    task.completion_note = None;
+                    reopened.push(path[..depth].to_vec());
+                }
+            }
+        }
+        if !reopened.is_empty() {
+            self.dirty = true;
+        }
+        reopened
+    }
+
+    pub fn find_item(&mut self, path: Vec<usize>) -> Option<&mut Task> {
+        if path.is_empty() {
+            return None;
+        }
+
+        let tasks = self.get_current_tasks();
+        let mut parent_list = tasks;
+
+        for &i in &path[..path.len() - 1] {
+            if let Some(task) = parent_list.get_mut(i) {
+                parent_list = &mut task.subtasks;
+            } else {
+                return None;
+            }
+        }
+
+        parent_list.get_mut(path[path.len() - 1])
+    }
+
+    fn complete_dfs(task: &mut Task) {
+        task.completed = true;
+        task.completed_at = Some(Utc::now());
+
+        for sub in task.subtasks.iter_mut() {
+            Self::complete_dfs(sub);
+        }
+    }
+
+    fn uncomplete_dfs(task: &mut Task) {
+        task.completed = false;
+        task.completed_at = None;
+        task.completion_note = None;
+
+        for sub in task.subtasks.iter_mut() {
+            Self::uncomplete_dfs(sub);
+        }
+    }
+
+    fn reset_dfs(task: &mut Task) {
+        task.id = new_task_id();
+        task.completed = false;
+        task.completed_at = None;
+        task.completion_note = None;
+        task.created_at = Utc::now();
+
+        for sub in task.subtasks.iter_mut() {
+            Self::reset_dfs(sub);
+        }
+    }
+
+    pub fn complete_task(
+        &mut self,
+        path: Vec<usize>,
+        keep_history: bool,
+        note: Option<&str>,
+    ) -> Result<bool, TmError> {
+        if !self.complete_in_place(&path, keep_history, note) {
+            return Ok(false);
+        }
+        self.dirty = true;
+        Ok(true)
+    }
+
+    /// Completes several root-level tasks and saves once. Returns whether each
+    /// index (in the order given) was found.
+    pub fn complete_many(
+        &mut self,
+        indices: &[usize],
+        keep_history: bool,
+        note: Option<&str>,
+    ) -> Result<Vec<bool>, TmError> {
+        let mut found = vec![false; indices.len()];
+        // Highest index first, so a recurring task inserting its next
+        // occurrence doesn't shift the tasks still to be completed
+        let mut order: Vec<usize> = (0..indices.len()).collect();
+        order.sort_by(|&a, &b| indices[b].cmp(&indices[a]));
+        for i in order {
+            found[i] = self.complete_in_place(&[indices[i]], keep_history, note);
+        }
+        if found.contains(&true) {
+            self.dirty = true;
+        }
+        Ok(found)
+    }
+
+    fn complete_in_place(
+        &mut self,
+        path: &[usize],
+        keep_history: bool,
+        note: Option<&str>,
+    ) -> bool {
+        let index = match path.last() {
+            Some(&index) => index,
+            None => return false,
+        };
+        let siblings = match self.sibling_list(path) {
+            Some(siblings) => siblings,
+            None => return false,
+        };
+        let task = match siblings.get_mut(index) {
+            Some(task) => task,
+            None => return false,
+        };
+
+        // Recurring tasks spawn a fresh occurrence with the due date advanced
+        let next = task.recurrence.map(|recurrence| {
+            let mut next = task.clone();
+            Self::reset_dfs(&mut next);
+            next.due = next.due.map(|due| recurrence.advance(due));
+            next
+        });
+
+        Self::complete_dfs(task);
+        if let Some(note) = note {
+            task.completion_note = Some(note.to_string());
+        }
+
+        if let Some(next) = next {
+            if keep_history {
+                // The completed instance stays behind as a one-off record
+                task.recurrence = None;
+                siblings.insert(index + 1, next);
+            } else {
+                siblings[index] = next;
+            }
+        }
+
+        true
+    }
+
+    pub fn uncomplete_task(&mut self, path: Vec<usize>) -> Result<bool, TmError> {
+        if let Some(task) = self.find_item(path) {
+            Self::uncomplete_dfs(task);
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn rename_task(&mut self, path: Vec<usize>, text: String) -> Result<bool, TmError> {
+        let text = Self::trimmed_text(&text)?;
+        if let Some(task) = self.find_item(path) {
+            task.text = text;
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Trims task text, rejecting it if nothing is left: a blank task renders
+    /// as an empty line and is hard to reference later.
+    fn trimmed_text(text: &str) -> Result<String, TmError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(TmError::EmptyText);
+        }
+        Ok(text.to_string())
+    }
+
+    /// Keeps the first line of a multi-line task as its text and appends
+    /// every other non-empty line as a new subtask, after any existing ones.
+    /// Returns how many subtasks were added, or `None` if there's no task at
+    /// `path`.
+    pub fn split_task(&mut self, path: Vec<usize>) -> Result<Option<usize>, TmError> {
+        let depth = path.len();
+        let task = match self.find_item(path) {
+            Some(task) => task,
+            None => return Ok(None),
+        };
+        let mut lines = task.text.lines().map(str::trim).filter(|l| !l.is_empty());
+        let first = lines.next().unwrap_or_default().to_string();
+        // Pasted lists often come with bullets, which would only be noise
+        let rest: Vec<Task> = lines
+            .map(|line| {
+                let text = line.strip_prefix("- ").or(line.strip_prefix("* "));
+                Task::new(text.unwrap_or(line).trim().to_string())
+            })
+            .collect();
+        if rest.is_empty() {
+            return Ok(Some(0));
+        }
+        if depth >= MAX_TASK_DEPTH {
+            return Err(TmError::TooDeep(MAX_TASK_DEPTH));
+        }
+
+        let added = rest.len();
+        task.text = first;
+        task.subtasks.extend(rest);
+        self.dirty = true;
+        Ok(Some(added))
+    }
+
+    pub fn set_priority(
+        &mut self,
+        path: Vec<usize>,
+        priority: Option<u8>,
+    ) -> Result<bool, TmError> {
+        if let Some(task) = self.find_item(path) {
+            task.priority = priority;
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn set_note(&mut self, path: Vec<usize>, note: Option<String>) -> Result<bool, TmError> {
+        if let Some(task) = self.find_item(path) {
+            task.note = note;
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn add_tags(&mut self, path: Vec<usize>, tags: Vec<String>) -> Result<bool, TmError> {
+        if let Some(task) = self.find_item(path) {
+            for tag in tags {
+                if !task.tags.contains(&tag) {
+                    task.tags.push(tag);
+                }
+            }
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn sibling_list(&mut self, path: &[usize]) -> Option<&mut Vec<Task>> {
+        match path.len() {
+            0 => None,
+            1 => Some(self.get_current_tasks()),
+            n => Some(&mut self.find_item(path[..n - 1].to_vec())?.subtasks),
+        }
+    }
+
+    fn remove_item(&mut self, path: Vec<usize>) -> Option<Task> {
+        let index = *path.last()?;
+        let siblings = self.sibling_list(&path)?;
+
+        if index < siblings.len() {
+            Some(siblings.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn delete_task(&mut self, path: Vec<usize>) -> Result<bool, TmError> {
+        if self.remove_item(path).is_some() {
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Deletes several root-level tasks and saves once. Returns whether each
+    /// index (in the order given) was found.
+    pub fn delete_many(&mut self, indices: &[usize]) -> Result<Vec<bool>, TmError> {
+        let mut found = vec![false; indices.len()];
+        // Highest index first, so removing a task doesn't shift the ones
+        // still to be deleted
+        let mut order: Vec<usize> = (0..indices.len()).collect();
+        order.sort_by(|&a, &b| indices[b].cmp(&indices[a]));
+        for i in order {
+            found[i] = self.remove_item(vec![indices[i]]).is_some();
+        }
+        if found.contains(&true) {
+            self.dirty = true;
+        }
+        Ok(found)
+    }
+
+    pub fn duplicate_task(&mut self, path: Vec<usize>) -> Result<bool, TmError> {
+        let index = match path.last() {
+            Some(&index) => index,
+            None => return Ok(false),
+        };
+        let siblings = match self.sibling_list(&path) {
+            Some(siblings) => siblings,
+            None => return Ok(false),
+        };
+
+        if let Some(original) = siblings.get(index) {
+            // The copy starts over as a fresh, incomplete task
+            let mut copy = original.clone();
+            Self::reset_dfs(&mut copy);
+            siblings.insert(index + 1, copy);
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn reparent(&mut self, from: Vec<usize>, to: Vec<usize>) -> Result<bool, TmError> {
+        // A task can't become a child of itself or of its own descendants
+        if from.is_empty() || to.starts_with(&from) {
+            return Ok(false);
+        }
+
+        match self.find_item(from.clone()) {
+            Some(task) => Self::check_depth(to.len(), task)?,
+            None => return Ok(false),
+        }
+        if !to.is_empty() && self.find_item(to.clone()).is_none() {
+            return Ok(false);
+        }
+
+        // Removing the task shifts later siblings, which may include an
+        // ancestor of the new parent
+        let mut to = to;
+        let depth = from.len() - 1;
+        if to.len() > depth && to[..depth] == from[..depth] && to[depth] > from[depth] {
+            to[depth] -= 1;
+        }
+
+        let task = self.remove_item(from).unwrap();
+        if to.is_empty() {
+            self.get_current_tasks().push(task);
+        } else {
+            self.find_item(to).unwrap().subtasks.push(task);
+        }
+        self.dirty = true;
+        Ok(true)
+    }
+
+    /// Moves a subtask (with its own subtasks) to the end of the root list.
+    pub fn promote(&mut self, path: Vec<usize>) -> Result<bool, TmError> {
+        if path.len() < 2 {
+            return Ok(false); // Already a root task
+        }
+        self.reparent(path, Vec::new())
+    }
+
+    /// Moves a task (with its subtasks) into the subtasks of `to_parent`, or
+    /// the root list when it's empty, so that it lands at `index`. The index
+    /// counts positions as they are before the task is taken out.
+    pub fn move_to(
+        &mut self,
+        from: Vec<usize>,
+        to_parent: Vec<usize>,
+        index: usize,
+    ) -> Result<bool, TmError> {
+        // A task can't become a child of itself or of its own descendants
+        if from.is_empty() || to_parent.starts_with(&from) {
+            return Ok(false);
+        }
+        match self.find_item(from.clone()) {
+            Some(task) => Self::check_depth(to_parent.len(), task)?,
+            None => return Ok(false),
+        }
+        match self.children_list(&to_parent) {
+            Some(siblings) if index <= siblings.len() => {}
+            _ => return Ok(false),
+        }
+
+        // Removing the task shifts later siblings, which may include an
+        // ancestor of the new parent or the target position itself
+        let mut to_parent = to_parent;
+        let mut index = index;
+        let depth = from.len() - 1;
+        if to_parent.len() > depth
+            && to_parent[..depth] == from[..depth]
+            && to_parent[depth] > from[depth]
+        {
+            to_parent[depth] -= 1;
+        } else if to_parent == from[..depth] && index > from[depth] {
+            index -= 1;
+        }
+
+        let task = self.remove_item(from).unwrap();
+        self.children_list(&to_parent).unwrap().insert(index, task);
+        self.dirty = true;
+        Ok(true)
+    }
+
+    /// Moves the task at `from` to just before (or after) the task at
+    /// `target`, under the target's parent. Returns false if either task is
+    /// missing or the move would put a task inside itself.
+    pub fn move_next_to(
+        &mut self,
+        from: Vec<usize>,
+        target: Vec<usize>,
+        after: bool,
+    ) -> Result<bool, TmError> {
+        let Some((&index, parent)) = target.split_last() else {
+            return Ok(false);
+        };
+        if self.find_item(target.clone()).is_none() {
+            return Ok(false);
+        }
+        let index = if after { index + 1 } else { index };
+        self.move_to(from, parent.to_vec(), index)
+    }
+
+    fn children_list(&mut self, parent: &[usize]) -> Option<&mut Vec<Task>> {
+        if parent.is_empty() {
+            Some(self.get_current_tasks())
+        } else {
+            Some(&mut self.find_item(parent.to_vec())?.subtasks)
+        }
+    }
+
+    pub fn move_task_to_project(
+        &mut self,
+        path: Vec<usize>,
+        target: String,
+    ) -> Result<(), TmError> {
+        if !self.store.projects.iter().any(|p| p.name == target) {
+            return Err(TmError::ProjectNotFound(target));
+        }
+
+        let task = self
+            .remove_item(path.clone())
+            .ok_or(TmError::PathNotFound(path))?;
+
+        if let Some(project) = self.store.projects.iter_mut().find(|p| p.name == target) {
+            project.tasks.push(task);
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn search_recursive(
+        tasks: &[Task],
+        query: &str,
+        path: &mut Vec<usize>,
+        matches: &mut Vec<(Vec<usize>, String)>,
+    ) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            if task.text.to_lowercase().contains(query) {
+                matches.push((path.clone(), task.text.clone()));
+            }
+            Self::search_recursive(&task.subtasks, query, path, matches);
+            path.pop();
+        }
+    }
+
+    pub fn search(&mut self, query: &str) -> Vec<(Vec<usize>, String)> {
+        let query = query.to_lowercase();
+        let mut matches = Vec::new();
+        Self::search_recursive(
+            self.get_current_tasks(),
+            &query,
+            &mut Vec::new(),
+            &mut matches,
+        );
+        matches
+    }
+
+    pub fn search_all_projects(&self, query: &str) -> Vec<(String, Vec<usize>, String)> {
+        let query = query.to_lowercase();
+        let mut results = Vec::new();
+        for project in &self.store.projects {
+            let mut matches = Vec::new();
+            Self::search_recursive(&project.tasks, &query, &mut Vec::new(), &mut matches);
+            results.extend(
+                matches
+                    .into_iter()
+                    .map(|(path, text)| (project.name.clone(), path, text)),
+            );
+        }
+        results
+    }
+
+    fn today_recursive<'a>(
+        tasks: &'a [Task],
+        today: NaiveDate,
+        overdue: bool,
+        path: &mut Vec<usize>,
+        matches: &mut PathMatches<'a>,
+    ) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            let relevant = match task.due {
+                // Due dates are stored as UTC midnight of the chosen day
+                Some(due) => {
+                    let due = due.date_naive();
+                    due == today || (overdue && !task.completed && due < today)
+                }
+                None => task.created_at.with_timezone(&Local).date_naive() == today,
+            };
+            if relevant {
+                matches.push((path.clone(), task));
+            }
+            Self::today_recursive(&task.subtasks, today, overdue, path, matches);
+            path.pop();
+        }
+    }
+
+    /// Tasks due today (or, without a due date, created today) grouped by
+    /// project; `overdue` also includes open tasks whose due date has passed.
+    pub fn today(&self, overdue: bool) -> Vec<(String, PathMatches<'_>)> {
+        let today = Local::now().date_naive();
+        let mut results = Vec::new();
+        for project in &self.store.projects {
+            let mut matches = Vec::new();
+            Self::today_recursive(
+                &project.tasks,
+                today,
+                overdue,
+                &mut Vec::new(),
+                &mut matches,
+            );
+            if !matches.is_empty() {
+                results.push((project.name.clone(), matches));
+            }
+        }
+        results
+    }
+
+    fn due_recursive<'a>(
+        tasks: &'a [Task],
+        today: NaiveDate,
+        path: &mut Vec<usize>,
+        matches: &mut PathMatches<'a>,
+    ) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            if !task.completed && task.due.is_some_and(|due| due.date_naive() <= today) {
+                matches.push((path.clone(), task));
+            }
+            Self::due_recursive(&task.subtasks, today, path, matches);
+            path.pop();
+        }
+    }
+
+    /// Open tasks due today or earlier, grouped by project.
+    pub fn due_open(&self) -> Vec<(String, PathMatches<'_>)> {
+        let today = Local::now().date_naive();
+        let mut results = Vec::new();
+        for project in &self.store.projects {
+            let mut matches = Vec::new();
+            Self::due_recursive(&project.tasks, today, &mut Vec::new(), &mut matches);
+            if !matches.is_empty() {
+                results.push((project.name.clone(), matches));
+            }
+        }
+        results
+    }
+
+    fn find_by_text_recursive(
+        tasks: &[Task],
+        text: &str,
+        path: &mut Vec<usize>,
+        matches: &mut Vec<Vec<usize>>,
+    ) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            if task.text.trim().to_lowercase() == text {
+                matches.push(path.clone());
+            }
+            Self::find_by_text_recursive(&task.subtasks, text, path, matches);
+            path.pop();
+        }
+    }
+
+    fn find_by_id_recursive(tasks: &[Task], id: &str, path: &mut Vec<usize>) -> bool {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            if task.id == id || Self::find_by_id_recursive(&task.subtasks, id, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    /// Returns the path of the task with this id in the current project.
+    pub fn find_by_id(&mut self, id: &str) -> Option<Vec<usize>> {
+        let mut path = Vec::new();
+        Self::find_by_id_recursive(self.get_current_tasks(), id.trim(), &mut path).then_some(path)
+    }
+
+    pub fn find_by_text(&mut self, text: &str) -> Vec<Vec<usize>> {
+        let text = text.trim().to_lowercase();
+        let mut matches = Vec::new();
+        Self::find_by_text_recursive(
+            self.get_current_tasks(),
+            &text,
+            &mut Vec::new(),
+            &mut matches,
+        );
+        matches
+    }
+
+    fn count_recursive(tasks: &[Task], include_completed: bool, recursive: bool) -> usize {
+        tasks
+            .iter()
+            .map(|task| {
+                let own = usize::from(include_completed || !task.completed);
+                let nested = if recursive {
+                    Self::count_recursive(&task.subtasks, include_completed, recursive)
+                } else {
+                    0
+                };
+                own + nested
+            })
+            .sum()
+    }
+
+    fn completion_ratio(task: &Task) -> (usize, usize) {
+        let total = Self::count_recursive(&task.subtasks, true, true);
+        let open = Self::count_recursive(&task.subtasks, false, true);
+        (total - open, total)
+    }
+
+    fn open_leaves(tasks: &[Task], path: &mut Vec<usize>, leaves: &mut Vec<(Vec<usize>, Task)>) {
+        for (index, task) in tasks.iter().enumerate() {
+            if task.completed {
+                continue;
+            }
+            path.push(index);
+            if task.subtasks.iter().all(|t| t.completed) {
+                leaves.push((path.clone(), task.clone()));
+            } else {
+                Self::open_leaves(&task.subtasks, path, leaves);
+            }
+            path.pop();
+        }
+    }
+
+    /// The open task to work on next: the most urgent open root task, or with
+    /// `deep` the most urgent open task that has no open subtasks. Ties go to
+    /// the task listed first.
+    pub fn next_task(&mut self, deep: bool) -> Option<(Vec<usize>, Task)> {
+        let tasks = self.get_current_tasks();
+        let candidates: Vec<(Vec<usize>, Task)> = if deep {
+            let mut leaves = Vec::new();
+            Self::open_leaves(tasks, &mut Vec::new(), &mut leaves);
+            leaves
+        } else {
+            tasks
+                .iter()
+                .enumerate()
+                .filter(|(_, task)| !task.completed)
+                .map(|(index, task)| (vec![index], task.clone()))
+                .collect()
+        };
+        // min_by returns the first of equal elements, keeping list order
+        candidates
+            .into_iter()
+            .min_by(|(_, a), (_, b)| Self::compare_tasks(a, b, SortKey::Priority))
+    }
+
+    fn collect_completed(
+        tasks: &[Task],
+        project: &str,
+        path: &mut Vec<usize>,
+        entries: &mut Vec<CompletedEntry>,
+    ) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            if let (true, Some(completed_at)) = (task.completed, task.completed_at) {
+                entries.push(CompletedEntry {
+                    project: project.to_string(),
+                    path: path.clone(),
+                    text: task.text.clone(),
+                    completed_at,
+                    note: task.completion_note.clone(),
                 });
             }
-        }
+            Self::collect_completed(&task.subtasks, project, path, entries);
+            path.pop();
+        }
+    }
+
+    /// Completed tasks, most recently completed first.
+    pub fn completed_log(&self, all_projects: bool) -> Vec<CompletedEntry> {
+        let current = self.get_current_project_name();
+        let mut entries = Vec::new();
+        for project in &self.store.projects {
+            if all_projects || project.name == current {
+                Self::collect_completed(
+                    &project.tasks,
+                    &project.name,
+                    &mut Vec::new(),
+                    &mut entries,
+                );
+            }
+        }
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.completed_at));
+        entries
+    }
+
+    pub fn count(&mut self, include_completed: bool, recursive: bool) -> usize {
+        Self::count_recursive(self.get_current_tasks(), include_completed, recursive)
+    }
+
+    fn collect_stats(tasks: &[Task], stats: &mut ProjectStats) {
+        for task in tasks {
+            stats.total += 1;
+            if task.completed {
+                stats.completed += 1;
+            } else if stats
+                .oldest_open
+                .is_none_or(|oldest| task.created_at < oldest)
+            {
+                stats.oldest_open = Some(task.created_at);
+            }
+            Self::collect_stats(&task.subtasks, stats);
+        }
+    }
+
+    pub fn stats_for(tasks: &[Task]) -> ProjectStats {
+        let mut stats = ProjectStats {
+            total: 0,
+            completed: 0,
+            oldest_open: None,
+        };
+        Self::collect_stats(tasks, &mut stats);
+        stats
+    }
+
+    pub fn stats(&mut self) -> ProjectStats {
+        Self::stats_for(self.get_current_tasks())
+    }
+
+    /// Counts tasks completed on or after `since`, and separately the
+    /// completed tasks that have no completion time to compare.
+    fn count_completed_since(tasks: &[Task], since: DateTime<Utc>, counts: &mut (usize, usize)) {
+        for task in tasks {
+            if task.completed {
+                match task.completed_at {
+                    Some(completed_at) if completed_at >= since => counts.0 += 1,
+                    Some(_) => {}
+                    None => counts.1 += 1,
+                }
+            }
+            Self::count_completed_since(&task.subtasks, since, counts);
+        }
+    }
+
+    pub fn completed_since(&mut self, since: DateTime<Utc>) -> (usize, usize) {
+        let mut counts = (0, 0);
+        Self::count_completed_since(self.get_current_tasks(), since, &mut counts);
+        counts
+    }
+
+    pub fn all_completed_since(&self, since: DateTime<Utc>) -> Vec<(String, (usize, usize))> {
+        self.store
+            .projects
+            .iter()
+            .map(|p| {
+                let mut counts = (0, 0);
+                Self::count_completed_since(&p.tasks, since, &mut counts);
+                (p.name.clone(), counts)
+            })
+            .collect()
+    }
+
+    pub fn all_project_stats(&self) -> Vec<(String, ProjectStats)> {
+        self.store
+            .projects
+            .iter()
+            .map(|p| (p.name.clone(), Self::stats_for(&p.tasks)))
+            .collect()
+    }
+
+    fn compare_tasks(a: &Task, b: &Task, key: SortKey) -> Ordering {
+        match key {
+            // Lower numbers are more urgent; tasks without a priority go last
+            SortKey::Priority => match (a.priority, b.priority) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Created => a.created_at.cmp(&b.created_at),
+            SortKey::Text => a.text.to_lowercase().cmp(&b.text.to_lowercase()),
+            // Open tasks have no completion time and go last
+            SortKey::Completed => match (a.completed_at, b.completed_at) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+
+    fn has_tag(task: &Task, tag: &str) -> bool {
+        task.tags.iter().any(|t| t == tag) || task.subtasks.iter().any(|t| Self::has_tag(t, tag))
+    }
+
+    fn has_status(task: &Task, completed: bool) -> bool {
+        task.completed == completed || task.subtasks.iter().any(|t| Self::has_status(t, completed))
+    }
+
+    fn print_tasks(tasks: &[Task], prefix: &str, depth: usize, options: &ListOptions) {
+        let markers = markers();
+        let (branch, last_branch, pipe) = (markers.branch, markers.last_branch, markers.pipe);
+
+        // Sorting only changes display order; indices still refer to storage
+        let mut order: Vec<usize> = (0..tasks.len()).collect();
+        if let Some(key) = options.sort {
+            order.sort_by(|&a, &b| Self::compare_tasks(&tasks[a], &tasks[b], key));
+        }
+        if options.reverse {
+            order.reverse();
+        }
+
+        // Keep ancestors of tagged tasks visible for context
+        if let Some(tag) = &options.tag {
+            order.retain(|&i| Self::has_tag(&tasks[i], tag));
+        }
+        if let Some(completed) = options.completed {
+            order.retain(|&i| Self::has_status(&tasks[i], completed));
+        }
+
+        for (position, &index) in order.iter().enumerate() {
+            let task = &tasks[index];
+            let is_last = position == order.len() - 1;
+            let connector = if is_last { last_branch } else { branch };
+            let child_prefix = format!("{}{}", prefix, if is_last { "   " } else { pipe });
+
+            // An open task is in progress once some, but not all, of the
+            // subtasks beneath it (at any depth) are done
+            let (done, total) = Self::completion_ratio(task);
+            let status = if task.completed {
+                markers.done.green()
+            } else if done > 0 && done < total {
+                markers.partial.yellow()
+            } else {
+                markers.open.red()
+            };
+            let show_subtasks = !task.subtasks.is_empty()
+                && options.max_depth.is_none_or(|max_depth| depth < max_depth);
+
+            let mut details = Vec::new();
+            if !task.subtasks.is_empty() {
+                details.push(format!("({}/{})", done, total).dimmed());
+                if !show_subtasks {
+                    details.push(format!("(+{} more)", total).dimmed());
+                }
+            }
+            if let Some(due) = task.due {
+                details.push(format!("(due {})", due.format("%Y-%m-%d")).dimmed());
+            }
+            if !task.tags.is_empty() {
+                details.push(task.tags.join(" ").cyan().dimmed());
+            }
+            if let Some(format) = options.times {
+                let time = task.completed_at.unwrap_or(task.created_at);
+                let time = match format {
+                    TimesFormat::Relative => humanize(time),
+                    TimesFormat::Absolute => time.format("%Y-%m-%d %H:%M").to_string(),
+                };
+                details.push(time.dimmed());
+            }
 
-        // Now safely get the current project's tasks
-        self.store
-            .projects
-            .iter_mut()
-            .find(|p| p.name == self.store.current_project)
-            .map(|p| &mut p.tasks)
-            .unwrap()
-    }
+            let head = format!("{}{}[{}]  {}.  ", prefix, connector, status, index);
+            let mut text = match task.priority {
+                Some(_) => format!("! {}", task.text),
+                None => task.text.clone(),
+            };
+            if let Some(max_width) = options.max_width {
+                // Measure without color codes; colored strings deref to their plain text
+                let used = display_width(&format!("{}{}[ ]  {}.  ", prefix, connector, index))
+                    + details.iter().map(|d| display_width(d) + 2).sum::<usize>();
+                text = truncate(&text, max_width.saturating_sub(used));
+            }
+            let text = match task.priority {
+                Some(1) => text.red().bold(),
+                Some(2) => text.yellow(),
+                _ => text.normal(),
+            };
 
-    pub fn add_task(
-        &mut self,
-        path: Vec<usize>,
-        text: String,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let task = Task {
-            text,
-            completed: false,
-            created_at: Utc::now(),
-            completed_at: None,
-            subtasks: Vec::new(),
-        };
+            let mut line = format!("{}{}", head, text);
+            for detail in details {
+                line.push_str(&format!("  {}", detail));
+            }
+            println!("{}", line);
 
-        let tasks = self.get_current_tasks();
-        if path.is_empty() {
-            tasks.push(task);
-            self.save()?;
-            Ok(true)
-        } else {
-            if let Some(parent) = self.find_item(path) {
-                parent.subtasks.push(task);
-                self.save()?;
-                Ok(true)
-            } else {
-                Ok(false)
+            if let Some(note) = &task.note {
+                let first_line = note.lines().next().unwrap_or("");
+                let preview: String = first_line.chars().take(NOTE_PREVIEW_LEN).collect();
+                let ellipsis = if preview.len() < note.trim_end().len() {
+                    markers.ellipsis
+                } else {
+                    ""
+                };
+                let guide = if show_subtasks { pipe } else { "   " };
+                println!(
+                    "{}{}     {}",
+                    child_prefix,
+                    guide,
+                    format!("{}{}", preview, ellipsis).dimmed()
+                );
             }
-        }
-    }
 
-    pub fn find_item(&mut self, path: Vec<usize>) -> Option<&mut Task> {
-        if path.is_empty() {
-            return None;
+            if show_subtasks {
+                Self::print_tasks(&task.subtasks, &child_prefix, depth + 1, options);
+            }
         }
+    }
 
-        let tasks = self.get_current_tasks();
-        let mut parent_list = tasks;
-
-        for &i in &path[..path.len() - 1] {
-            if let Some(task) = parent_list.get_mut(i) {
-                parent_list = &mut task.subtasks;
-            } else {
-                return None;
+    fn render_markdown(tasks: &[Task], depth: usize, timestamps: bool, out: &mut String) {
+        for task in tasks {
+            let checkbox = if task.completed { "[x]" } else { "[ ]" };
+            out.push_str(&format!(
+                "{}- {} {}",
+                "  ".repeat(depth),
+                checkbox,
+                task.text
+            ));
+            if timestamps {
+                match task.completed_at {
+                    Some(completed_at) => out.push_str(&format!(
+                        " _(completed {})_",
+                        completed_at.format("%Y-%m-%d")
+                    )),
+                    None => out.push_str(&format!(
+                        " _(created {})_",
+                        task.created_at.format("%Y-%m-%d")
+                    )),
+                }
             }
+            out.push('\n');
+            Self::render_markdown(&task.subtasks, depth + 1, timestamps, out);
         }
+    }
 
-        parent_list.get_mut(path[path.len() - 1])
+    pub fn tasks_to_markdown(tasks: &[Task]) -> String {
+        let mut out = String::new();
+        Self::render_markdown(tasks, 0, false, &mut out);
+        out
     }
 
-    fn complete_dfs(task: &mut Task) {
-        task.completed = true;
-        task.completed_at = Some(Utc::now());
+    /// One `<x|o>\t<path>\t<text>` line per task, depth-first. Scripts rely
+    /// on this format, so it must only ever change in compatible ways.
+    pub fn tasks_to_porcelain(tasks: &[Task]) -> String {
+        let mut out = String::new();
+        Self::render_porcelain(tasks, &mut Vec::new(), &mut out);
+        out
+    }
 
-        for sub in task.subtasks.iter_mut() {
-            Self::complete_dfs(sub);
+    fn render_porcelain(tasks: &[Task], path: &mut Vec<usize>, out: &mut String) {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            // Escaped so multi-line text (e.g. from --editor) stays on one line
+            let text = task
+                .text
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\r', "\\r")
+                .replace('\n', "\\n");
+            let status = if task.completed { 'x' } else { 'o' };
+            out.push_str(&format!("{}\t{}\t{}\n", status, format_path(path), text));
+            Self::render_porcelain(&task.subtasks, path, out);
+            path.pop();
         }
     }
 
-    fn uncomplete_dfs(task: &mut Task) {
-        task.completed = false;
-        task.completed_at = None;
+    pub fn export_markdown(&self, project: &str) -> Option<String> {
+        let tasks = self.list_project_tasks(project)?;
+        let mut out = format!("# {}\n\n", project);
+        Self::render_markdown(tasks, 0, true, &mut out);
+        Some(out)
+    }
 
-        for sub in task.subtasks.iter_mut() {
-            Self::uncomplete_dfs(sub);
-        }
+    pub fn export_json(&self) -> Result<String, TmError> {
+        Ok(serde_json::to_string_pretty(&self.store)?)
     }
 
-    pub fn complete_task(&mut self, path: Vec<usize>) -> Result<bool, Box<dyn std::error::Error>> {
-        if let Some(task) = self.find_item(path) {
-            Self::complete_dfs(task);
-            self.save()?;
-            Ok(true)
-        } else {
-            Ok(false)
+    fn write_csv_rows(
+        writer: &mut csv::Writer<Vec<u8>>,
+        project: &str,
+        tasks: &[Task],
+        path: &mut Vec<usize>,
+    ) -> Result<(), TmError> {
+        for (index, task) in tasks.iter().enumerate() {
+            path.push(index);
+            writer.write_record([
+                project,
+                &format_path(path),
+                &task.text,
+                &task.completed.to_string(),
+                &task.created_at.to_rfc3339(),
+                &task
+                    .completed_at
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default(),
+                &(path.len() - 1).to_string(),
+            ])?;
+            Self::write_csv_rows(writer, project, &task.subtasks, path)?;
+            path.pop();
         }
+        Ok(())
     }
 
-    pub fn uncomplete_task(&mut self, path: Vec<usize>) -> Result<bool, Box<dyn std::error::Error>> {
-        if let Some(task) = self.find_item(path) {
-            Self::uncomplete_dfs(task);
-            self.save()?;
-            Ok(true)
-        } else {
-            Ok(false)
+    pub fn export_csv(&self, project: Option<&str>) -> Result<Option<String>, TmError> {
+        let projects: Vec<&Project> = match project {
+            Some(name) => match self.store.projects.iter().find(|p| p.name == name) {
+                Some(project) => vec![project],
+                None => return Ok(None),
+            },
+            None => self.store.projects.iter().collect(),
+        };
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record([
+            "project",
+            "path",
+            "text",
+            "completed",
+            "created_at",
+            "completed_at",
+            "depth",
+        ])?;
+        for project in projects {
+            Self::write_csv_rows(&mut writer, &project.name, &project.tasks, &mut Vec::new())?;
         }
+
+        let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
     }
 
-    pub fn delete_task(&mut self, path: Vec<usize>) -> Result<bool, Box<dyn std::error::Error>> {
-        if path.is_empty() {
-            return Ok(false);
+    pub fn import_json(&mut self, content: &str, merge: bool) -> Result<usize, TmError> {
+        let mut imported: ProjectStore = serde_json::from_str(content)?;
+        let count = imported.projects.len();
+        for project in &imported.projects {
+            for task in project.tasks.iter().chain(&project.archived) {
+                Self::check_depth(0, task)?;
+            }
         }
 
-        let tasks = self.get_current_tasks();
-        if path.len() == 1 {
-            let index = path[0];
-            if index < tasks.len() {
-                tasks.remove(index);
-                self.save()?;
-                Ok(true)
-            } else {
-                Ok(false)
+        // Imported ids can repeat ones already here (e.g. re-importing an
+        // export) or each other, and would make `--id` ambiguous
+        let mut seen = HashSet::new();
+        if merge {
+            for project in &self.store.projects {
+                Self::collect_ids(&project.tasks, &mut seen);
+                Self::collect_ids(&project.archived, &mut seen);
             }
-        } else {
-            let parent_path = path[..path.len() - 1].to_vec();
-            let index = path[path.len() - 1];
-
-            if let Some(parent) = self.find_item(parent_path) {
-                if index < parent.subtasks.len() {
-                    parent.subtasks.remove(index);
-                    self.save()?;
-                    Ok(true)
-                } else {
-                    Ok(false)
+        }
+        for project in &mut imported.projects {
+            Self::assign_unique_ids(&mut project.tasks, &mut seen);
+            Self::assign_unique_ids(&mut project.archived, &mut seen);
+        }
+
+        if merge {
+            for project in imported.projects {
+                match self
+                    .store
+                    .projects
+                    .iter_mut()
+                    .find(|p| p.name == project.name)
+                {
+                    Some(existing) => {
+                        existing.tasks.extend(project.tasks);
+                        existing.archived.extend(project.archived);
+                    }
+                    None => self.store.projects.push(project),
                 }
-            } else {
-                Ok(false)
             }
+        } else {
+            self.store = imported;
+            self.ensure_current_project();
         }
+
+        self.dirty = true;
+        Ok(count)
     }
 
-    fn print_tasks(tasks: &Vec<Task>, depth: usize) {
-        let indent = "  ".repeat(depth + 3);
-        for (index, task) in tasks.iter().enumerate() {
-            let status = if task.completed {
-                "✓".green()
-            } else {
-                "○".red()
-            };
-            println!("{}[{}]  {}.  {}", indent, status, index, task.text);
+    /// Parses an indented outline (two spaces or one tab per level, `[x]`
+    /// marking done items) and appends it to the current project.
+    pub fn import_text(&mut self, content: &str) -> Result<usize, TmError> {
+        let mut imported = Vec::new();
+        // Tasks whose subtasks may still follow, outermost first
+        let mut open: Vec<Task> = Vec::new();
+        let mut count = 0;
 
-            if !task.subtasks.is_empty() {
-                Self::print_tasks(&task.subtasks, depth + 1);
+        for line in content.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let level = indent.matches('\t').count() + indent.matches(' ').count() / 2;
+
+            let mut text = line.trim();
+            let mut completed = false;
+            if let Some(rest) = text.strip_prefix("[x]").or(text.strip_prefix("[X]")) {
+                text = rest.trim_start();
+                completed = true;
+            } else if let Some(rest) = text.strip_prefix("[ ]") {
+                text = rest.trim_start();
+            }
+
+            // A line can be at most one level deeper than the one before it
+            while open.len() > level {
+                let done = open.pop().unwrap();
+                match open.last_mut() {
+                    Some(parent) => parent.subtasks.push(done),
+                    None => imported.push(done),
+                }
+            }
+            if open.len() >= MAX_TASK_DEPTH {
+                return Err(TmError::TooDeep(MAX_TASK_DEPTH));
+            }
+
+            let mut task = Task::new(text.to_string());
+            if completed {
+                task.completed = true;
+                task.completed_at = Some(Utc::now());
+            }
+            open.push(task);
+            count += 1;
+        }
+        while let Some(done) = open.pop() {
+            match open.last_mut() {
+                Some(parent) => parent.subtasks.push(done),
+                None => imported.push(done),
             }
         }
+
+        self.get_current_tasks().extend(imported);
+        self.dirty = true;
+        Ok(count)
     }
 
-    pub fn list_tasks(&mut self) {
-        let tasks = self.get_current_tasks();
+    pub fn print_task_list(tasks: &[Task], options: &ListOptions) {
         if tasks.is_empty() {
             println!("      list is empty.");
         } else {
-            Self::print_tasks(tasks, 0);
+            Self::print_tasks(tasks, "      ", 0, options);
         }
     }
 
-    pub fn clear_completed(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn clear_completed(&mut self) -> Result<(), TmError> {
         let tasks = self.get_current_tasks();
         Self::clear_completed_recursive(tasks);
-        self.save()?;
+        self.dirty = true;
         Ok(())
     }
 
@@ -247,18 +1758,123 @@ impl TaskStore {
         }
     }
 
-    pub fn clear_all(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Removes completed tasks without subtasks of the current project, at
+    /// any depth. Returns how many were removed.
+    pub fn prune_completed(&mut self) -> Result<usize, TmError> {
+        let removed = Self::prune_completed_leaves(self.get_current_tasks());
+        if removed > 0 {
+            self.dirty = true;
+        }
+        Ok(removed)
+    }
+
+    /// Parents are kept even when all their subtasks get pruned: they were
+    /// not leaves to begin with and often group related work.
+    fn prune_completed_leaves(tasks: &mut Vec<Task>) -> usize {
+        let before = tasks.len();
+        tasks.retain(|t| !(t.completed && t.subtasks.is_empty()));
+        let mut removed = before - tasks.len();
+        for task in tasks.iter_mut() {
+            removed += Self::prune_completed_leaves(&mut task.subtasks);
+        }
+        removed
+    }
+
+    /// Moves completed tasks out of `tasks` (at any depth) into `taken`. With
+    /// a cutoff, only tasks completed before it are taken.
+    fn take_completed(tasks: &mut Vec<Task>, taken: &mut Vec<Task>, cutoff: Option<DateTime<Utc>>) {
+        let mut i = 0;
+        while i < tasks.len() {
+            let old_enough = match (cutoff, tasks[i].completed_at) {
+                (Some(cutoff), Some(completed_at)) => completed_at < cutoff,
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+            if tasks[i].completed && old_enough {
+                taken.push(tasks.remove(i));
+            } else {
+                Self::take_completed(&mut tasks[i].subtasks, taken, cutoff);
+                i += 1;
+            }
+        }
+    }
+
+    /// Archives tasks in every project that were completed more than `days`
+    /// days ago. Returns how many were archived.
+    pub fn auto_archive(&mut self, days: u32) -> usize {
+        let cutoff = Utc::now() - chrono::Duration::days(days.into());
+        let mut count = 0;
+        for project in &mut self.store.projects {
+            let mut taken = Vec::new();
+            Self::take_completed(&mut project.tasks, &mut taken, Some(cutoff));
+            count += taken.len();
+            project.archived.extend(taken);
+        }
+        if count > 0 {
+            self.dirty = true;
+        }
+        count
+    }
+
+    pub fn archive_completed(&mut self) -> Result<usize, TmError> {
+        let project = self.get_current_project();
+        let mut taken = Vec::new();
+        Self::take_completed(&mut project.tasks, &mut taken, None);
+        let count = taken.len();
+        project.archived.extend(taken);
+        self.dirty = true;
+        Ok(count)
+    }
+
+    pub fn archived_tasks(&mut self) -> &Vec<Task> {
+        &self.get_current_project().archived
+    }
+
+    pub fn restore_archived(&mut self, index: usize) -> Result<bool, TmError> {
+        let project = self.get_current_project();
+        if index < project.archived.len() {
+            let task = project.archived.remove(index);
+            project.tasks.push(task);
+            self.dirty = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Marks every open task of the current project completed, at any
+    /// depth. Tasks that were already done keep their completion time.
+    /// Returns how many tasks were completed.
+    pub fn complete_all(&mut self) -> Result<usize, TmError> {
+        let count = Self::complete_open(self.get_current_tasks());
+        if count > 0 {
+            self.dirty = true;
+        }
+        Ok(count)
+    }
+
+    fn complete_open(tasks: &mut [Task]) -> usize {
+        let mut count = 0;
+        for task in tasks {
+            if !task.completed {
+                task.completed = true;
+                task.completed_at = Some(Utc::now());
+                task.completion_note = None;
+                count += 1;
+            }
+            count += Self::complete_open(&mut task.subtasks);
+        }
+        count
+    }
+
+    pub fn clear_all(&mut self) -> Result<(), TmError> {
         let tasks = self.get_current_tasks();
         tasks.clear();
-        self.save()?;
+        self.dirty = true;
         Ok(())
     }
 
-    pub fn move_task(
-        &mut self,
-        path: Vec<usize>,
-        direction: &str,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
+    pub fn move_task(&mut self, path: Vec<usize>, direction: &str) -> Result<bool, TmError> {
         if path.is_empty() {
             return Ok(false);
         }
@@ -316,7 +1932,7 @@ impl TaskStore {
                 task_list.len() - 1
             }
             _ => {
-                // Try to parse as a number for absolute positioning
+                // Absolute positioning: the final index among the siblings
                 match direction.parse::<usize>() {
                     Ok(pos) => {
                         if pos >= task_list.len() {
@@ -329,73 +1945,460 @@ impl TaskStore {
             }
         };
 
-        // Perform the swap
+        // new_index is where the task ends up once the others close the gap
         if new_index != index {
-            task_list.swap(index, new_index);
-            self.save()?;
+            let task = task_list.remove(index);
+            task_list.insert(new_index, task);
+            self.dirty = true;
         }
 
         Ok(true)
     }
 
     // Project management methods
-    pub fn create_project(&mut self, name: String) -> Result<bool, Box<dyn std::error::Error>> {
+    pub fn create_project(&mut self, name: String, color: Option<String>) -> Result<bool, TmError> {
+        validate_project_name(&name).map_err(TmError::InvalidProjectName)?;
         if self.store.projects.iter().any(|p| p.name == name) {
             return Ok(false); // Project already exists
         }
 
         self.store.projects.push(Project {
-            name: name.clone(),
-            tasks: Vec::new(),
-            created_at: Utc::now(),
+            color,
+            ..Project::new(name.clone())
         });
         // Persist the new project so subsequent CLI invocations can see it
-        self.save()?;
+        self.dirty = true;
         Ok(true)
     }
 
-    pub fn switch_project(&mut self, name: String) -> Result<bool, Box<dyn std::error::Error>> {
+    pub fn switch_project(&mut self, name: String) -> Result<bool, TmError> {
         if self.store.projects.iter().any(|p| p.name == name) {
             self.store.current_project = name;
-            self.save()?;
+            self.dirty = true;
             Ok(true)
         } else {
             Ok(false) // Project doesn't exist
         }
     }
 
-    pub fn list_projects(&self) {
-        for project in &self.store.projects {
+    pub fn rename_project(&mut self, old: String, new: String) -> Result<bool, TmError> {
+        if old == "default" {
+            return Ok(false); // Cannot rename default project
+        }
+        validate_project_name(&new).map_err(TmError::InvalidProjectName)?;
+
+        if self.store.projects.iter().any(|p| p.name == new) {
+            return Ok(false); // Target name already taken
+        }
+
+        let project = self
+            .store
+            .projects
+            .iter_mut()
+            .find(|p| p.name == old)
+            .ok_or_else(|| TmError::ProjectNotFound(old.clone()))?;
+        project.name = new.clone();
+
+        // Keep the active project pointing at the renamed project
+        if self.project_context.as_ref() == Some(&old) {
+            self.project_context = Some(new.clone());
+        }
+        if self.store.current_project == old {
+            self.store.current_project = new;
+        }
+
+        self.dirty = true;
+        Ok(true)
+    }
+
+    pub fn set_project_color(&mut self, name: &str, color: Option<String>) -> Result<(), TmError> {
+        let project = self
+            .store
+            .projects
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or_else(|| TmError::ProjectNotFound(name.to_string()))?;
+        project.color = color;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn project_color(&self, name: &str) -> Option<&str> {
+        self.store
+            .projects
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.color.as_deref())
+    }
+
+    pub fn project_names(&self) -> Vec<String> {
+        self.store.projects.iter().map(|p| p.name.clone()).collect()
+    }
+
+    fn compare_projects(a: &Project, b: &Project, key: ProjectSortKey) -> Ordering {
+        match key {
+            ProjectSortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            ProjectSortKey::Created => a.created_at.cmp(&b.created_at),
+            ProjectSortKey::Tasks => {
+                let open = |p: &Project| Self::stats_for(&p.tasks).open();
+                open(b).cmp(&open(a))
+            }
+        }
+    }
+
+    pub fn list_projects(&self, sort: Option<ProjectSortKey>) {
+        let mut projects: Vec<&Project> = self.store.projects.iter().collect();
+        if let Some(key) = sort {
+            projects.sort_by(|a, b| Self::compare_projects(a, b, key));
+        }
+
+        let width = projects
+            .iter()
+            .map(|p| display_width(&p.name))
+            .max()
+            .unwrap_or(0);
+        for project in projects {
             let marker = if project.name == self.store.current_project {
                 " * ".green()
             } else {
                 "   ".normal()
             };
-            println!("{}{}", marker, project.name);
+            let stats = Self::stats_for(&project.tasks);
+            let counts = format!("({}/{})", stats.open(), stats.total);
+            let name = pad(&project.name, width);
+            let name = match &project.color {
+                Some(color) => name.color(color.as_str()),
+                None => name.normal(),
+            };
+            println!("{}{}  {}", marker, name, counts.dimmed());
         }
     }
 
-    pub fn delete_project(&mut self, name: String) -> Result<bool, Box<dyn std::error::Error>> {
+    /// Deletes a project. If it was the current one, `switch_to` (or
+    /// "default" when not given) becomes current.
+    pub fn delete_project(
+        &mut self,
+        name: String,
+        switch_to: Option<String>,
+    ) -> Result<bool, TmError> {
         if name == "default" {
             return Ok(false); // Cannot delete default project
         }
 
-        if let Some(pos) = self.store.projects.iter().position(|p| p.name == name) {
-            self.store.projects.remove(pos);
+        let pos = self
+            .store
+            .projects
+            .iter()
+            .position(|p| p.name == name)
+            .ok_or_else(|| TmError::ProjectNotFound(name.clone()))?;
 
-            // If we deleted the current project, switch to default
-            if self.store.current_project == name {
-                self.store.current_project = "default".to_string();
-            }
+        if self.store.projects.len() == 1 {
+            return Ok(false); // Always keep at least one project
+        }
 
-            self.save()?;
-            Ok(true)
-        } else {
-            Ok(false) // Project doesn't exist
+        let landing = switch_to.unwrap_or_else(|| "default".to_string());
+        if landing != "default" && !self.store.projects.iter().any(|p| p.name == landing) {
+            return Err(TmError::ProjectNotFound(landing));
+        }
+
+        self.store.projects.remove(pos);
+
+        // If we deleted the current project, switch to the landing one
+        if self.store.current_project == name {
+            self.store.current_project = landing;
         }
+        self.ensure_current_project();
+
+        self.dirty = true;
+        Ok(true)
+    }
+
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
     }
 
     pub fn get_current_project_name(&self) -> &str {
-        &self.store.current_project
+        // Fall back to the saved project if the context one has since gone away
+        match &self.project_context {
+            Some(name) if self.store.projects.iter().any(|p| &p.name == name) => name,
+            _ => &self.store.current_project,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with(texts: &[&str]) -> TaskStore {
+        let mut store = TaskStore::with_path(PathBuf::from("tasks.json"));
+        for text in texts {
+            store
+                .add_task(Vec::new(), Task::new(text.to_string()), None)
+                .unwrap();
+        }
+        store
+    }
+
+    /// Path of a tasks file in a new, empty temporary directory.
+    fn temp_file() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tm-test-{}", new_task_id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("tasks.json")
+    }
+
+    #[test]
+    fn created_project_survives_a_reload() {
+        let path = temp_file();
+        let mut store = TaskStore::with_path(path.clone());
+        store.load().unwrap();
+        assert!(store.create_project("work".to_string(), None).unwrap());
+        store.flush().unwrap();
+        drop(store);
+
+        let mut reloaded = TaskStore::with_path(path);
+        reloaded.load().unwrap();
+        assert!(reloaded.project_names().contains(&"work".to_string()));
+    }
+
+    #[test]
+    fn loading_a_current_file_leaves_it_untouched() {
+        let path = temp_file();
+        let mut store = TaskStore::with_path(path.clone());
+        store.load().unwrap();
+        store
+            .add_task(Vec::new(), Task::new("a".to_string()), None)
+            .unwrap();
+        store.flush().unwrap();
+        drop(store);
+        let content = fs::read_to_string(&path).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let mut store = TaskStore::with_path(path.clone());
+        store.load().unwrap();
+        store.flush().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn load_repairs_inconsistent_completion_times() {
+        let path = temp_file();
+        fs::write(
+            &path,
+            r#"{
+              "current_project": "default",
+              "projects": [{
+                "name": "default",
+                "created_at": "2025-01-01T00:00:00Z",
+                "tasks": [
+                  {"id": "a", "text": "done", "completed": true,
+                   "created_at": "2025-01-02T00:00:00Z", "completed_at": null, "subtasks": []},
+                  {"id": "b", "text": "open", "completed": false,
+                   "created_at": "2025-01-03T00:00:00Z",
+                   "completed_at": "2025-01-04T00:00:00Z", "subtasks": []}
+                ]
+              }]
+            }"#,
+        )
+        .unwrap();
+        let mut store = TaskStore::with_path(path);
+        store.load().unwrap();
+        let tasks = store.get_current_tasks();
+        assert_eq!(tasks[0].completed_at, Some(tasks[0].created_at));
+        assert_eq!(tasks[1].completed_at, None);
+    }
+
+    #[test]
+    fn adding_under_a_completed_task_reopens_its_ancestors() {
+        let mut store = store_with(&["a"]);
+        store
+            .add_task(vec![0], Task::new("b".to_string()), None)
+            .unwrap();
+        store
+            .add_task(vec![0, 0], Task::new("c".to_string()), None)
+            .unwrap();
+        for path in [vec![0, 0, 0], vec![0, 0], vec![0]] {
+            assert!(store.complete_task(path, true, Some("shipped")).unwrap());
+        }
+
+        store
+            .add_task(vec![0, 0, 0], Task::new("d".to_string()), None)
+            .unwrap();
+        let reopened = store.reopen_ancestors(&[0, 0, 0]);
+        assert_eq!(reopened, [vec![0], vec![0, 0], vec![0, 0, 0]]);
+        for path in reopened {
+            let task = store.find_item(path).unwrap();
+            assert!(!task.completed);
+            assert_eq!(task.completed_at, None);
+            assert_eq!(task.completion_note, None);
+        }
+    }
+
+    #[test]
+    fn blank_task_text_is_rejected() {
+        let mut store = store_with(&["a"]);
+        for text in ["", "   "] {
+            assert!(matches!(
+                store.add_task(Vec::new(), Task::new(text.to_string()), None),
+                Err(TmError::EmptyText)
+            ));
+            assert!(matches!(
+                store.rename_task(vec![0], text.to_string()),
+                Err(TmError::EmptyText)
+            ));
+        }
+        assert_eq!(texts(&mut store), ["a"]);
+    }
+
+    #[test]
+    fn task_text_is_trimmed() {
+        let mut store = store_with(&["  a  "]);
+        store.rename_task(vec![0], "\tb \n".to_string()).unwrap();
+        store
+            .add_task(Vec::new(), Task::new(" c".to_string()), None)
+            .unwrap();
+        assert_eq!(texts(&mut store), ["b", "c"]);
+    }
+
+    #[test]
+    fn deleting_every_project_keeps_a_current_one() {
+        let mut store = store_with(&[]);
+        for name in ["a", "b"] {
+            store.create_project(name.to_string(), None).unwrap();
+        }
+        assert!(store.switch_project("b".to_string()).unwrap());
+        for name in ["a", "b"] {
+            assert!(store.delete_project(name.to_string(), None).unwrap());
+        }
+        // The default project is refused
+        assert!(!store.delete_project("default".to_string(), None).unwrap());
+        assert!(matches!(
+            store.delete_project("a".to_string(), None),
+            Err(TmError::ProjectNotFound(_))
+        ));
+        assert_eq!(store.get_current_project_name(), "default");
+        assert!(store.get_current_tasks().is_empty());
+
+        // So is the last remaining project, even when it isn't the default
+        store.store = ProjectStore {
+            current_project: "solo".to_string(),
+            projects: vec![Project::new("solo".to_string())],
+        };
+        assert!(!store.delete_project("solo".to_string(), None).unwrap());
+        assert_eq!(store.project_names(), ["solo"]);
+        assert_eq!(store.get_current_project_name(), "solo");
+    }
+
+    #[test]
+    fn default_project_config_keeps_the_default_project() {
+        let mut store = TaskStore::with_path(temp_file());
+        store.with_config(&Config {
+            default_project: Some("work".to_string()),
+            ..Config::default()
+        });
+        store.load().unwrap();
+        assert_eq!(store.get_current_project_name(), "work");
+        assert_eq!(store.project_names(), ["default", "work"]);
+        store.dirty = true;
+        store.flush().unwrap();
+        assert!(store.check_data().unwrap().is_empty());
+    }
+
+    fn texts(store: &mut TaskStore) -> Vec<String> {
+        store
+            .get_current_tasks()
+            .iter()
+            .map(|task| task.text.clone())
+            .collect()
+    }
+
+    /// A chain of tasks `levels` deep, as a one-project export.
+    fn nested_export(levels: usize) -> String {
+        let mut task = Task::new("leaf".to_string());
+        for _ in 1..levels {
+            let mut parent = Task::new("parent".to_string());
+            parent.subtasks.push(task);
+            task = parent;
+        }
+        let mut project = Project::new("default".to_string());
+        project.tasks.push(task);
+        serde_json::to_string(&ProjectStore {
+            current_project: "default".to_string(),
+            projects: vec![project],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn import_json_enforces_the_depth_limit() {
+        let mut store = store_with(&[]);
+        assert!(store
+            .import_json(&nested_export(MAX_TASK_DEPTH), false)
+            .is_ok());
+        assert!(matches!(
+            store.import_json(&nested_export(MAX_TASK_DEPTH + 1), true),
+            Err(TmError::TooDeep(MAX_TASK_DEPTH))
+        ));
+    }
+
+    #[test]
+    fn add_task_enforces_the_depth_limit() {
+        let mut store = store_with(&[]);
+        store
+            .import_json(&nested_export(MAX_TASK_DEPTH - 1), false)
+            .unwrap();
+        let deepest = vec![0; MAX_TASK_DEPTH - 1];
+        assert!(store
+            .add_task(deepest.clone(), Task::new("ok".to_string()), None)
+            .unwrap());
+        let too_deep = vec![0; MAX_TASK_DEPTH];
+        assert!(matches!(
+            store.add_task(too_deep, Task::new("no".to_string()), None),
+            Err(TmError::TooDeep(MAX_TASK_DEPTH))
+        ));
+    }
+
+    #[test]
+    fn move_task_position_is_the_final_index() {
+        let mut store = store_with(&["a", "b", "c", "d"]);
+        assert!(store.move_task(vec![0], "3").unwrap());
+        assert_eq!(texts(&mut store), ["b", "c", "d", "a"]);
+
+        let mut store = store_with(&["a", "b", "c", "d"]);
+        assert!(store.move_task(vec![3], "1").unwrap());
+        assert_eq!(texts(&mut store), ["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn move_task_rejects_positions_past_the_end() {
+        let mut store = store_with(&["a", "b"]);
+        assert!(!store.move_task(vec![0], "2").unwrap());
+        assert_eq!(texts(&mut store), ["a", "b"]);
+    }
+
+    #[test]
+    fn move_next_to_places_before_and_after_at_the_boundaries() {
+        let cases: [(usize, usize, bool, [&str; 4]); 6] = [
+            (3, 0, false, ["d", "a", "b", "c"]),
+            (0, 3, false, ["b", "c", "a", "d"]),
+            (3, 0, true, ["a", "d", "b", "c"]),
+            (0, 3, true, ["b", "c", "d", "a"]),
+            (1, 1, false, ["a", "b", "c", "d"]),
+            (1, 1, true, ["a", "b", "c", "d"]),
+        ];
+        for (from, target, after, expected) in cases {
+            let mut store = store_with(&["a", "b", "c", "d"]);
+            assert!(store.move_next_to(vec![from], vec![target], after).unwrap());
+            assert_eq!(texts(&mut store), expected, "{} next to {}", from, target);
+        }
+    }
+
+    #[test]
+    fn move_next_to_rejects_a_missing_target() {
+        let mut store = store_with(&["a", "b"]);
+        assert!(!store.move_next_to(vec![0], vec![2], true).unwrap());
+        assert!(!store.move_next_to(vec![0], vec![2], false).unwrap());
+        assert_eq!(texts(&mut store), ["a", "b"]);
     }
 }
@@ -1,10 +1,111 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use colored::{Color, ColoredString, Colorize};
 use std::fs;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-pub fn get_data_file_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let data_dir = dirs::data_dir().ok_or("could not determine data directory")?;
+use crate::error::TmError;
 
-    let app_dir = data_dir.join("tm");
+const MAX_PROJECT_NAME_LEN: usize = 64;
+
+/// Every non-ASCII symbol tm prints, so terminals that can't render them can
+/// switch to plain stand-ins in one place.
+pub struct Markers {
+    pub done: &'static str,
+    pub open: &'static str,
+    pub partial: &'static str,
+    pub branch: &'static str,
+    pub last_branch: &'static str,
+    pub pipe: &'static str,
+    pub ellipsis: &'static str,
+    pub separator: &'static str,
+    pub dash: &'static str,
+    pub bullet: &'static str,
+    pub check: &'static str,
+    pub success: &'static str,
+    pub warning: &'static str,
+    pub failure: &'static str,
+    pub progress: &'static str,
+}
+
+const UNICODE_MARKERS: Markers = Markers {
+    done: "✓",
+    open: "○",
+    partial: "◐",
+    branch: "├─ ",
+    last_branch: "└─ ",
+    pipe: "│  ",
+    ellipsis: "…",
+    separator: "·",
+    dash: "—",
+    bullet: "•",
+    check: "✓",
+    success: "✅",
+    warning: "⚠️ ",
+    failure: "❌",
+    progress: "🔄",
+};
+
+const ASCII_MARKERS: Markers = Markers {
+    done: "x",
+    open: " ",
+    partial: "~",
+    branch: "|- ",
+    last_branch: "`- ",
+    pipe: "|  ",
+    ellipsis: "...",
+    separator: "-",
+    dash: "-",
+    bullet: "*",
+    check: "+",
+    success: "+",
+    warning: "!",
+    failure: "x",
+    progress: ">",
+};
+
+static ASCII: AtomicBool = AtomicBool::new(false);
+
+pub fn set_ascii(ascii: bool) {
+    ASCII.store(ascii, Ordering::Relaxed);
+}
+
+pub fn markers() -> &'static Markers {
+    if ASCII.load(Ordering::Relaxed) {
+        &ASCII_MARKERS
+    } else {
+        &UNICODE_MARKERS
+    }
+}
+
+/// Guesses whether the terminal can't show Unicode: TM_ASCII=1 forces it, and
+/// a dumb or Linux console TERM or a non-UTF-8 locale suggests it.
+pub fn detect_ascii() -> bool {
+    let env = |name: &str| std::env::var(name).unwrap_or_default();
+    if let Ok(value) = std::env::var("TM_ASCII") {
+        return value == "1" || value == "true";
+    }
+    if matches!(env("TERM").as_str(), "dumb" | "linux") {
+        return true;
+    }
+    // The first locale variable that is set wins, as in libc
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .map(|name| env(name))
+        .find(|value| !value.is_empty());
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            !(locale.contains("utf-8") || locale.contains("utf8"))
+        }
+        None => false,
+    }
+}
+
+pub fn get_data_file_path() -> Result<PathBuf, TmError> {
+    let app_dir = get_data_directory()?;
 
     if !app_dir.exists() {
         fs::create_dir_all(&app_dir)?;
@@ -13,14 +114,296 @@ pub fn get_data_file_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
     Ok(app_dir.join("tasks.json"))
 }
 
-pub fn get_data_directory() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    let data_dir = dirs::data_dir().ok_or("could not determine data directory")?;
+pub fn get_data_directory() -> Result<PathBuf, TmError> {
+    // An explicit TM_DATA_DIR takes precedence over the platform default
+    if let Some(dir) = std::env::var_os("TM_DATA_DIR").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let data_dir = dirs::data_dir()
+        .or_else(fallback_data_dir)
+        .ok_or(TmError::DataDirUnavailable)?;
     Ok(data_dir.join("tm"))
 }
 
-pub fn format_path(path: &Vec<usize>) -> String {
+fn fallback_data_dir() -> Option<PathBuf> {
+    // The platform lookup can fail in minimal containers; the usual
+    // environment variables are often still set there
+    let (var, subdir) = if cfg!(windows) {
+        ("APPDATA", "")
+    } else {
+        ("HOME", ".local/share")
+    };
+    std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join(subdir))
+}
+
+pub fn get_legacy_data_file_path() -> Option<PathBuf> {
+    // Data location used by the tool before it was renamed from td to tm
+    dirs::data_dir().map(|dir| dir.join("td").join("todos.json"))
+}
+
+pub fn format_path(path: &[usize]) -> String {
     path.iter()
         .map(|i| i.to_string())
         .collect::<Vec<_>>()
         .join(".")
 }
+
+/// Parses path arguments where each one may itself be dotted, e.g. `0.1 2`.
+pub fn parse_path(args: &[String]) -> Result<Vec<usize>, String> {
+    let mut path = Vec::new();
+    for index in args.iter().flat_map(|arg| arg.split('.')) {
+        path.push(
+            index
+                .parse::<usize>()
+                .map_err(|_| format!("invalid index '{}'", index))?,
+        );
+    }
+    Ok(path)
+}
+
+/// Expands a single `start..end` argument into the root indices it covers,
+/// both ends included. Returns `None` when the arguments aren't a range.
+pub fn parse_range(args: &[String]) -> Option<Result<Vec<usize>, String>> {
+    let (start, end) = match args {
+        [arg] => arg.split_once("..")?,
+        _ => return None,
+    };
+    let bounds = start.parse::<usize>().ok().zip(end.parse::<usize>().ok());
+    Some(match bounds {
+        Some((start, end)) if start <= end => Ok((start..=end).collect()),
+        _ => Err(format!(
+            "invalid range '{}', expected START..END with START <= END",
+            args[0]
+        )),
+    })
+}
+
+/// Returns the first http(s) URL in `text`, without trailing punctuation
+/// such as a closing parenthesis or full stop.
+pub fn find_url(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .map(|word| word.trim_start_matches(['(', '<', '"', '\'']))
+        .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+        .map(|url| url.trim_end_matches([')', '>', '"', '\'', '.', ',', ';', ':', '!', '?']))
+}
+
+/// Checks that `name` can be shown in banners and `list-projects` without
+/// breaking the layout: not empty, at most 64 characters and free of control
+/// characters such as newlines.
+pub fn validate_project_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        Err("project name can't be empty".to_string())
+    } else if name.chars().count() > MAX_PROJECT_NAME_LEN {
+        Err(format!(
+            "project name is longer than {} characters",
+            MAX_PROJECT_NAME_LEN
+        ))
+    } else if name.chars().any(char::is_control) {
+        Err("project name can't contain control characters like newlines or tabs".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Renders how long ago `dt` was in the largest whole unit, e.g. "5m ago",
+/// "3h ago", "2d ago" or "3w ago". Anything under a minute (or in the
+/// future, from clock skew) is "just now".
+pub fn humanize(dt: DateTime<Utc>) -> String {
+    humanize_at(dt, Utc::now())
+}
+
+/// `humanize` measured from `now` rather than the current time.
+pub fn humanize_at(dt: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now - dt;
+    if elapsed.num_weeks() > 0 {
+        format!("{}w ago", elapsed.num_weeks())
+    } else if elapsed.num_days() > 0 {
+        format!("{}d ago", elapsed.num_days())
+    } else if elapsed.num_hours() > 0 {
+        format!("{}h ago", elapsed.num_hours())
+    } else if elapsed.num_minutes() > 0 {
+        format!("{}m ago", elapsed.num_minutes())
+    } else {
+        "just now".to_string()
+    }
+}
+
+/// Columns `text` takes up in a terminal; wide characters such as CJK or
+/// emoji count as two.
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Pads `text` with spaces to `width` columns.
+pub fn pad(text: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        text,
+        " ".repeat(width.saturating_sub(display_width(text)))
+    )
+}
+
+/// Shortens `text` to at most `width` columns, ending in `…` when cut.
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let ellipsis = markers().ellipsis;
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let c_width = UnicodeWidthChar::width(c).unwrap_or(0);
+        // Leave room for the ellipsis
+        if used + c_width + display_width(ellipsis) > width {
+            break;
+        }
+        truncated.push(c);
+        used += c_width;
+    }
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+/// Width of the terminal stdout is attached to, or None when it is piped.
+pub fn terminal_width() -> Option<usize> {
+    // Only stdout counts: terminal_size() would fall back to stderr and still
+    // truncate `tm list > file` run from a terminal
+    terminal_size::terminal_size_of(std::io::stdout())
+        .map(|(terminal_size::Width(width), _)| width as usize)
+}
+
+/// Validates a color name against the ones `colored` knows, e.g. "blue" or
+/// "bright magenta".
+pub fn parse_color(s: &str) -> Result<String, String> {
+    Color::from_str(s)
+        .map(|_| s.to_lowercase())
+        .map_err(|_| format!("unknown color '{}'", s))
+}
+
+/// A project name in its own color, or the usual green.
+pub fn paint_project(name: &str, color: Option<&str>) -> ColoredString {
+    match color {
+        Some(color) => name.color(color),
+        None => name.green(),
+    }
+}
+
+pub fn parse_due_date(s: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        .map_err(|_| format!("invalid date '{}', expected YYYY-MM-DD", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn display_width_counts_wide_characters_twice() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("🎉"), 2);
+    }
+
+    #[test]
+    fn pad_lines_up_wide_and_ascii_names() {
+        assert_eq!(
+            display_width(&pad("日本", 8)),
+            display_width(&pad("work", 8))
+        );
+        assert_eq!(pad("日本", 8), "日本    ");
+    }
+
+    #[test]
+    fn truncate_stays_within_width_without_splitting_characters() {
+        set_ascii(false);
+        let text = "日本語のタスク🎉と説明";
+        for width in 1..display_width(text) {
+            let truncated = truncate(text, width);
+            assert!(display_width(&truncated) <= width, "{:?}", truncated);
+            let kept = truncated.trim_end_matches('…');
+            assert!(text.starts_with(kept), "{:?}", truncated);
+        }
+        assert_eq!(truncate(text, display_width(text)), text);
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_range_includes_both_ends() {
+        assert_eq!(parse_range(&args(&["2..5"])), Some(Ok(vec![2, 3, 4, 5])));
+        assert_eq!(parse_range(&args(&["3..3"])), Some(Ok(vec![3])));
+    }
+
+    #[test]
+    fn parse_range_rejects_backwards_and_malformed_ranges() {
+        for arg in ["5..2", "a..3", "2..", "..5", "1...4"] {
+            assert!(
+                matches!(parse_range(&args(&[arg])), Some(Err(_))),
+                "{}",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn parse_range_ignores_plain_paths() {
+        assert_eq!(parse_range(&args(&["2"])), None);
+        assert_eq!(parse_range(&args(&["0.1"])), None);
+        assert_eq!(parse_range(&args(&["2..5", "1"])), None);
+    }
+
+    #[test]
+    fn validate_project_name_accepts_ordinary_names() {
+        for name in [
+            "work",
+            "home office",
+            "Q3 — launch",
+            "日本語",
+            &"x".repeat(64),
+        ] {
+            assert!(validate_project_name(name).is_ok(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn validate_project_name_rejects_unusable_names() {
+        for name in [
+            "",
+            "   ",
+            &"x".repeat(65),
+            "two\nlines",
+            "tab\there",
+            "bell\u{7}",
+        ] {
+            assert!(validate_project_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn humanize_at_uses_the_largest_whole_unit() {
+        let now = Utc.with_ymd_and_hms(2025, 6, 15, 12, 0, 0).unwrap();
+        let cases = [
+            (Duration::seconds(-30), "just now"),
+            (Duration::zero(), "just now"),
+            (Duration::seconds(59), "just now"),
+            (Duration::seconds(60), "1m ago"),
+            (Duration::minutes(59), "59m ago"),
+            (Duration::minutes(60), "1h ago"),
+            (Duration::hours(23), "23h ago"),
+            (Duration::hours(24), "1d ago"),
+            (Duration::days(6), "6d ago"),
+            (Duration::days(7), "1w ago"),
+            (Duration::days(20), "2w ago"),
+        ];
+        for (ago, expected) in cases {
+            assert_eq!(humanize_at(now - ago, now), expected, "{} ago", ago);
+        }
+    }
+}